@@ -1,15 +1,27 @@
 use crate::boardstructs::{Square, Direction, Rank, File, Shiftable};
 use std::convert::From;
+use std::fmt;
 use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign};
 
+const NOT_A_FILE: u64 = 0xfefefefefefefefe;
+const NOT_H_FILE: u64 = 0x7f7f7f7f7f7f7f7f;
+
 #[derive(PartialEq, Clone, Copy)]
 pub struct Bitboard(u64);
 
 impl Bitboard {
-    pub fn new() -> Self {
+    pub const LIGHT_SQUARES: Bitboard = Bitboard(0x55aa55aa55aa55aa);
+    pub const DARK_SQUARES: Bitboard = Bitboard(0xaa55aa55aa55aa55);
+
+    pub const fn new() -> Self {
         Self(0)
     }
 
+    // const counterpart to From<u64>, for building lookup tables at compile time
+    pub const fn from_u64(value: u64) -> Self {
+        Self(value)
+    }
+
     pub fn len(self) -> u8 {
         self.0.count_ones() as u8
     }
@@ -63,9 +75,155 @@ impl Bitboard {
 
     pub fn remove(&mut self, sqr: Square) -> bool {
         let contains = self.contains(sqr);
-        *self = self.union(Self::from(sqr).invert());
+        *self = self.intersection(Self::from(sqr).invert());
         contains
     }
+
+    // more than one square set, cheaper than checking len() > 1
+    pub fn more_than_one(self) -> bool {
+        self.0 & self.0.wrapping_sub(1) != 0
+    }
+
+    // least significant square, the one closest to a1
+    pub fn lsb(self) -> Option<Square> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(Square::new(self.0.trailing_zeros() as u8))
+        }
+    }
+
+    // most significant square, the one closest to h8
+    pub fn msb(self) -> Option<Square> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(Square::new(63 - self.0.leading_zeros() as u8))
+        }
+    }
+
+    pub fn pop_lsb(&mut self) -> Option<Square> {
+        let lsb = self.lsb();
+        self.0 &= self.0.wrapping_sub(1);
+        lsb
+    }
+}
+
+// Flips and mirrors, see
+// https://www.chessprogramming.org/Flipping_Mirroring_and_Rotating
+impl Bitboard {
+    // rank 1 <-> rank 8
+    pub fn flip_vertical(self) -> Self {
+        Self(self.0.swap_bytes())
+    }
+
+    // file a <-> file h
+    pub fn mirror_horizontal(self) -> Self {
+        const K1: u64 = 0x5555555555555555;
+        const K2: u64 = 0x3333333333333333;
+        const K4: u64 = 0x0f0f0f0f0f0f0f0f;
+        let mut x = self.0;
+        x = ((x >> 1) & K1) | ((x & K1) << 1);
+        x = ((x >> 2) & K2) | ((x & K2) << 2);
+        x = ((x >> 4) & K4) | ((x & K4) << 4);
+        Self(x)
+    }
+
+    // flip about the a1-h8 diagonal, so a8 <-> h1
+    pub fn flip_diagonal(self) -> Self {
+        const K1: u64 = 0x5500550055005500;
+        const K2: u64 = 0x3333000033330000;
+        const K4: u64 = 0x0f0f0f0f00000000;
+        let mut x = self.0;
+        let mut t = K4 & (x ^ (x << 28));
+        x ^= t ^ (t >> 28);
+        t = K2 & (x ^ (x << 14));
+        x ^= t ^ (t >> 14);
+        t = K1 & (x ^ (x << 7));
+        x ^= t ^ (t >> 7);
+        Self(x)
+    }
+
+    // flip about the a8-h1 diagonal, so a1 <-> h8
+    pub fn flip_anti_diagonal(self) -> Self {
+        const K1: u64 = 0xaa00aa00aa00aa00;
+        const K2: u64 = 0xcccc0000cccc0000;
+        const K4: u64 = 0xf0f0f0f00f0f0f0f;
+        let mut x = self.0;
+        let mut t = x ^ (x << 36);
+        x ^= K4 & (t ^ (x >> 36));
+        t = K2 & (x ^ (x << 18));
+        x ^= t ^ (t >> 18);
+        t = K1 & (x ^ (x << 9));
+        x ^= t ^ (t >> 9);
+        Self(x)
+    }
+}
+
+// Fills, see https://www.chessprogramming.org/Kogge-Stone_Algorithm
+impl Bitboard {
+    // Smears every square in direction for as long as it stays on empty
+    // squares. The squares themselves are included, the first non-empty square
+    // hit is not.
+    pub fn occluded_fill(self, direction: Direction, empty: Bitboard) -> Self {
+        // how far one step moves a bit, and the squares a step can't land on
+        // without wrapping around the board
+        let (step, wrap_mask): (i8, u64) = match direction {
+            Direction::Up => (8, !0),
+            Direction::UpRight => (9, NOT_A_FILE),
+            Direction::Right => (1, NOT_A_FILE),
+            Direction::DownRight => (-7, NOT_A_FILE),
+            Direction::Down => (-8, !0),
+            Direction::DownLeft => (-9, NOT_H_FILE),
+            Direction::Left => (-1, NOT_H_FILE),
+            Direction::UpLeft => (7, NOT_H_FILE),
+        };
+        let shift = |x: u64, n: i8| if n > 0 { x << n } else { x >> -n };
+        let mut gen = self.0;
+        let mut pro = empty.0 & wrap_mask;
+        gen |= pro & shift(gen, step);
+        pro &= shift(pro, step);
+        gen |= pro & shift(gen, 2 * step);
+        pro &= shift(pro, 2 * step);
+        gen |= pro & shift(gen, 4 * step);
+        Self(gen)
+    }
+
+    // Smears every square in direction to the edge of the board
+    pub fn fill(self, direction: Direction) -> Self {
+        self.occluded_fill(direction, Self(!0))
+    }
+
+    // every file with a square in it
+    pub fn file_fill(self) -> Self {
+        self.fill(Direction::Up) | self.fill(Direction::Down)
+    }
+
+    // every rank with a square in it
+    pub fn rank_fill(self) -> Self {
+        self.fill(Direction::Left) | self.fill(Direction::Right)
+    }
+}
+
+impl fmt::Debug for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bitboard({:#018x})", self.0)
+    }
+}
+
+// An 8x8 grid from white's side, with rank 8 at the top
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..8).rev() {
+            write!(f, "{}", rank + 1)?;
+            for file in 0..8 {
+                let sqr = Square::from_coords(Rank::new(rank), File::new(file));
+                write!(f, " {}", if self.contains(sqr) { 'X' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "  a b c d e f g h")
+    }
 }
 
 impl From<Bitboard> for u64 {
@@ -145,13 +303,7 @@ impl Iterator for BitboardIntoIterator {
     type Item = Square;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.bb.is_empty() {
-            return None;
-        }
-        let idx = self.bb.0.trailing_zeros();
-        let sqr = Square::new(idx as u8);
-        self.bb.remove(sqr);
-        Some(sqr)
+        self.bb.pop_lsb()
     }
 }
 
@@ -162,22 +314,206 @@ impl Shiftable for Bitboard {
 
     fn shift(&self, direction: Direction) -> Self::ShiftType {
         match direction {
-            Direction::Up => { let maskedbb = *self & Self::from(Rank::new(7));
-                    Self(maskedbb.0 << 8)
+            Direction::Up => {
+                let maskedbb = *self & Self::from(Rank::new(7)).invert();
+                Self(maskedbb.0 << 8)
+            }
+            Direction::Right => {
+                let maskedbb = *self & Self::from(File::new(7)).invert();
+                Self(maskedbb.0 << 1)
+            }
+            Direction::Down => {
+                let maskedbb = *self & Self::from(Rank::new(0)).invert();
+                Self(maskedbb.0 >> 8)
             }
-            Direction::Right => { let maskedbb = *self & Self::from(File::new(7));
-                    Self(maskedbb.0 << 1)
+            Direction::Left => {
+                let maskedbb = *self & Self::from(File::new(0)).invert();
+                Self(maskedbb.0 >> 1)
             }
-            Direction::Down => { let maskedbb = *self & Self::from(Rank::new(0));
-                    Self(maskedbb.0 >> 8)
+            Direction::UpRight => self.shift(Direction::Up).shift(Direction::Right),
+            Direction::DownRight => self.shift(Direction::Down).shift(Direction::Right),
+            Direction::DownLeft => self.shift(Direction::Down).shift(Direction::Left),
+            Direction::UpLeft => self.shift(Direction::Up).shift(Direction::Left),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTIONS: [Direction; 8] = [
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+        Direction::UpLeft,
+    ];
+
+    // a handful of irregular boards to check square-by-square references on
+    const SAMPLES: [u64; 5] = [
+        0x0000000000000001,
+        0x8000000000000080,
+        0x0042001818004200,
+        0x123456789abcdef0,
+        0xffff00000000ffff,
+    ];
+
+    fn sqr(s: &str) -> Square {
+        Square::try_from_str(s).unwrap()
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut bb = Bitboard::new();
+        assert!(!bb.insert(sqr("e4")));
+        assert!(bb.insert(sqr("e4")));
+        bb.insert(sqr("a1"));
+        assert!(bb.remove(sqr("e4")));
+        assert!(!bb.remove(sqr("e4")));
+        assert_eq!(bb, Bitboard::from(sqr("a1")));
+    }
+
+    #[test]
+    fn rank_and_file_boards() {
+        assert_eq!(u64::from(Bitboard::from(Rank::new(0))), 0x00000000000000ff);
+        assert_eq!(u64::from(Bitboard::from(Rank::new(7))), 0xff00000000000000);
+        assert_eq!(u64::from(Bitboard::from(File::new(0))), 0x0101010101010101);
+        assert_eq!(u64::from(Bitboard::from(File::new(7))), 0x8080808080808080);
+    }
+
+    #[test]
+    fn square_colors() {
+        assert!(Bitboard::DARK_SQUARES.contains(sqr("a1")));
+        assert!(Bitboard::LIGHT_SQUARES.contains(sqr("h1")));
+        assert!(Bitboard::DARK_SQUARES.contains(sqr("h8")));
+        assert!(Bitboard::LIGHT_SQUARES.contains(sqr("d1")));
+        assert!(Bitboard::LIGHT_SQUARES.is_disjoint(Bitboard::DARK_SQUARES));
+        assert_eq!(
+            Bitboard::LIGHT_SQUARES | Bitboard::DARK_SQUARES,
+            Bitboard::from(!0)
+        );
+    }
+
+    #[test]
+    fn bit_scans() {
+        let mut bb = Bitboard::from(sqr("c3")) | Bitboard::from(sqr("f7"));
+        assert!(bb.more_than_one());
+        assert_eq!(bb.lsb(), Some(sqr("c3")));
+        assert_eq!(bb.msb(), Some(sqr("f7")));
+        assert_eq!(bb.pop_lsb(), Some(sqr("c3")));
+        assert!(!bb.more_than_one());
+        assert_eq!(bb.pop_lsb(), Some(sqr("f7")));
+        assert_eq!(bb.pop_lsb(), None);
+        assert_eq!(bb.lsb(), None);
+        assert_eq!(bb.msb(), None);
+        assert!(!bb.more_than_one());
+    }
+
+    #[test]
+    fn iterator() {
+        let bb = Bitboard::from(0x8000000000000081);
+        let squares = bb.into_iter().collect::<Vec<Square>>();
+        assert_eq!(squares, vec![sqr("a1"), sqr("h1"), sqr("h8")]);
+    }
+
+    #[test]
+    fn flips() {
+        for idx in 0..64 {
+            let s = Square::new(idx);
+            let (rank, file) = (u8::from(s.rank()), u8::from(s.file()));
+            let bb = Bitboard::from(s);
+            let at =
+                |rank, file| Bitboard::from(Square::from_coords(Rank::new(rank), File::new(file)));
+            assert_eq!(bb.flip_vertical(), at(7 - rank, file));
+            assert_eq!(bb.mirror_horizontal(), at(rank, 7 - file));
+            assert_eq!(bb.flip_diagonal(), at(file, rank));
+            assert_eq!(bb.flip_anti_diagonal(), at(7 - file, 7 - rank));
+        }
+        for &sample in &SAMPLES {
+            let bb = Bitboard::from(sample);
+            assert_eq!(bb.flip_vertical().flip_vertical(), bb);
+            assert_eq!(bb.mirror_horizontal().mirror_horizontal(), bb);
+            assert_eq!(bb.flip_diagonal().flip_diagonal(), bb);
+            assert_eq!(bb.flip_anti_diagonal().flip_anti_diagonal(), bb);
+        }
+    }
+
+    #[test]
+    fn shifts_match_square_shifts() {
+        for &sample in &SAMPLES {
+            let bb = Bitboard::from(sample);
+            for &direction in &DIRECTIONS {
+                let mut expected = Bitboard::new();
+                for s in bb {
+                    if let Some(shifted) = s.shift(direction) {
+                        expected.insert(shifted);
+                    }
+                }
+                assert_eq!(bb.shift(direction), expected);
             }
-            Direction::Left => { let maskedbb = *self & Self::from(File::new(0));
-                    Self(maskedbb.0 >> 1)
+        }
+    }
+
+    #[test]
+    fn occluded_fills_match_square_walks() {
+        for &sample in &SAMPLES {
+            let gen = Bitboard::from(sample);
+            for &empty in &SAMPLES {
+                let empty = Bitboard::from(empty).invert();
+                for &direction in &DIRECTIONS {
+                    let mut expected = gen;
+                    for s in gen {
+                        let mut destination = s.shift(direction);
+                        while let Some(d) = destination {
+                            if !empty.contains(d) {
+                                break;
+                            }
+                            expected.insert(d);
+                            destination = d.shift(direction);
+                        }
+                    }
+                    assert_eq!(gen.occluded_fill(direction, empty), expected);
+                }
             }
-            Direction::UpRight => { self.shift(Direction::Up).shift(Direction::Right) }
-            Direction::DownRight => { self.shift(Direction::Down).shift(Direction::Right) }
-            Direction::DownLeft => { self.shift(Direction::Up).shift(Direction::Left) }
-            Direction::UpLeft => { self.shift(Direction::Up).shift(Direction::Left) }
         }
     }
+
+    #[test]
+    fn rank_and_file_fills() {
+        let bb = Bitboard::from(sqr("b2")) | Bitboard::from(sqr("g7"));
+        assert_eq!(
+            bb.file_fill(),
+            Bitboard::from(File::new(1)) | Bitboard::from(File::new(6))
+        );
+        assert_eq!(
+            bb.rank_fill(),
+            Bitboard::from(Rank::new(1)) | Bitboard::from(Rank::new(6))
+        );
+        assert_eq!(
+            Bitboard::from(sqr("d4")).fill(Direction::UpRight),
+            Bitboard::from(0x8040201008000000)
+        );
+    }
+
+    #[test]
+    fn display() {
+        let bb = Bitboard::from(sqr("a1")) | Bitboard::from(sqr("e4")) | Bitboard::from(sqr("h8"));
+        assert_eq!(
+            bb.to_string(),
+            "8 . . . . . . . X\n\
+             7 . . . . . . . .\n\
+             6 . . . . . . . .\n\
+             5 . . . . . . . .\n\
+             4 . . . . X . . .\n\
+             3 . . . . . . . .\n\
+             2 . . . . . . . .\n\
+             1 X . . . . . . .\n  \
+             a b c d e f g h"
+        );
+        assert_eq!(format!("{:?}", bb), "Bitboard(0x8000000010000001)");
+    }
 }
@@ -1,15 +1,71 @@
 use crate::bitboard::Bitboard;
+use crate::piece::Color;
 use std::convert::From;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::str::FromStr;
 
 // Rank
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct Rank(u8);
 
 impl Rank {
-    pub fn new(value: u8) -> Rank {
+    pub const ALL: [Rank; 8] = [
+        Rank(0),
+        Rank(1),
+        Rank(2),
+        Rank(3),
+        Rank(4),
+        Rank(5),
+        Rank(6),
+        Rank(7),
+    ];
+
+    pub const fn new(value: u8) -> Rank {
         assert!(value < 8);
         Rank(value)
     }
+
+    // For hot paths where value is known to be in range, only checked in
+    // debug builds. A bad value is a bug but not undefined behaviour, since
+    // every table indexed with it is bounds checked
+    pub const fn new_debug_checked(value: u8) -> Rank {
+        debug_assert!(value < 8);
+        Rank(value)
+    }
+
+    // The rank as seen from color's side of the board, so that the first
+    // rank is always color's back rank
+    pub const fn relative_to(self, color: Color) -> Rank {
+        match color {
+            Color::White => self,
+            Color::Black => Rank(7 - self.0),
+        }
+    }
+
+    // The squares on this rank, from the a-file to the h-file
+    pub fn squares(self) -> impl Iterator<Item = Square> {
+        File::ALL
+            .iter()
+            .map(move |&file| Square::from_coords(self, file))
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", (b'1' + self.0) as char)
+    }
+}
+
+impl FromStr for Rank {
+    type Err = SquareError;
+
+    fn from_str(s: &str) -> Result<Rank, SquareError> {
+        match s.as_bytes() {
+            &[c @ b'1'..=b'8'] => Ok(Rank(c - b'1')),
+            _ => Err(SquareError::InvalidString(String::from(s))),
+        }
+    }
 }
 
 impl From<Rank> for u8 {
@@ -21,7 +77,7 @@ impl From<Rank> for u8 {
 impl From<Rank> for Bitboard {
     fn from(rank: Rank) -> Bitboard {
         let mut bb = Bitboard::new();
-        for file in 0..7 {
+        for file in 0..8 {
             bb.insert(Square::from_coords(rank, File::new(file)));
         }
         bb
@@ -33,10 +89,53 @@ impl From<Rank> for Bitboard {
 pub struct File(u8);
 
 impl File {
-    pub fn new(value: u8) -> File {
+    pub const ALL: [File; 8] = [
+        File(0),
+        File(1),
+        File(2),
+        File(3),
+        File(4),
+        File(5),
+        File(6),
+        File(7),
+    ];
+
+    pub const fn new(value: u8) -> File {
         assert!(value < 8);
         File(value)
     }
+
+    // For hot paths where value is known to be in range, only checked in
+    // debug builds. A bad value is a bug but not undefined behaviour, since
+    // every table indexed with it is bounds checked
+    pub const fn new_debug_checked(value: u8) -> File {
+        debug_assert!(value < 8);
+        File(value)
+    }
+
+    // The squares on this file, from the first rank to the eighth
+    pub fn squares(self) -> impl Iterator<Item = Square> {
+        Rank::ALL
+            .iter()
+            .map(move |&rank| Square::from_coords(rank, self))
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", (b'a' + self.0) as char)
+    }
+}
+
+impl FromStr for File {
+    type Err = SquareError;
+
+    fn from_str(s: &str) -> Result<File, SquareError> {
+        match s.as_bytes() {
+            &[c @ b'a'..=b'h'] => Ok(File(c - b'a')),
+            _ => Err(SquareError::InvalidString(String::from(s))),
+        }
+    }
 }
 
 impl From<File> for u8 {
@@ -48,7 +147,7 @@ impl From<File> for u8 {
 impl From<File> for Bitboard {
     fn from(file: File) -> Bitboard {
         let mut bb = Bitboard::new();
-        for rank in 0..7 {
+        for rank in 0..8 {
             bb.insert(Square::from_coords(Rank::new(rank), file));
         }
         bb
@@ -60,12 +159,99 @@ impl From<File> for Bitboard {
 pub struct Square(u8);
 
 impl Square {
-    pub fn new(value: u8) -> Square {
+    pub const ALL: [Square; 64] = {
+        let mut all = [Square(0); 64];
+        let mut idx = 0;
+        while idx < 64 {
+            all[idx] = Square(idx as u8);
+            idx += 1;
+        }
+        all
+    };
+
+    pub const fn new(value: u8) -> Square {
         assert!(value <= 63);
         Square(value)
     }
+
+    // For hot paths where value is known to be in range, only checked in
+    // debug builds. A bad value is a bug but not undefined behaviour, since
+    // every table indexed with it is bounds checked
+    pub const fn new_debug_checked(value: u8) -> Square {
+        debug_assert!(value <= 63);
+        Square(value)
+    }
 }
 
+#[rustfmt::skip]
+impl Square {
+    pub const A1: Square = Square(0);
+    pub const B1: Square = Square(1);
+    pub const C1: Square = Square(2);
+    pub const D1: Square = Square(3);
+    pub const E1: Square = Square(4);
+    pub const F1: Square = Square(5);
+    pub const G1: Square = Square(6);
+    pub const H1: Square = Square(7);
+    pub const A2: Square = Square(8);
+    pub const B2: Square = Square(9);
+    pub const C2: Square = Square(10);
+    pub const D2: Square = Square(11);
+    pub const E2: Square = Square(12);
+    pub const F2: Square = Square(13);
+    pub const G2: Square = Square(14);
+    pub const H2: Square = Square(15);
+    pub const A3: Square = Square(16);
+    pub const B3: Square = Square(17);
+    pub const C3: Square = Square(18);
+    pub const D3: Square = Square(19);
+    pub const E3: Square = Square(20);
+    pub const F3: Square = Square(21);
+    pub const G3: Square = Square(22);
+    pub const H3: Square = Square(23);
+    pub const A4: Square = Square(24);
+    pub const B4: Square = Square(25);
+    pub const C4: Square = Square(26);
+    pub const D4: Square = Square(27);
+    pub const E4: Square = Square(28);
+    pub const F4: Square = Square(29);
+    pub const G4: Square = Square(30);
+    pub const H4: Square = Square(31);
+    pub const A5: Square = Square(32);
+    pub const B5: Square = Square(33);
+    pub const C5: Square = Square(34);
+    pub const D5: Square = Square(35);
+    pub const E5: Square = Square(36);
+    pub const F5: Square = Square(37);
+    pub const G5: Square = Square(38);
+    pub const H5: Square = Square(39);
+    pub const A6: Square = Square(40);
+    pub const B6: Square = Square(41);
+    pub const C6: Square = Square(42);
+    pub const D6: Square = Square(43);
+    pub const E6: Square = Square(44);
+    pub const F6: Square = Square(45);
+    pub const G6: Square = Square(46);
+    pub const H6: Square = Square(47);
+    pub const A7: Square = Square(48);
+    pub const B7: Square = Square(49);
+    pub const C7: Square = Square(50);
+    pub const D7: Square = Square(51);
+    pub const E7: Square = Square(52);
+    pub const F7: Square = Square(53);
+    pub const G7: Square = Square(54);
+    pub const H7: Square = Square(55);
+    pub const A8: Square = Square(56);
+    pub const B8: Square = Square(57);
+    pub const C8: Square = Square(58);
+    pub const D8: Square = Square(59);
+    pub const E8: Square = Square(60);
+    pub const F8: Square = Square(61);
+    pub const G8: Square = Square(62);
+    pub const H8: Square = Square(63);
+}
+
+// Also used for ranks and files that fail to parse
 #[derive(Debug)]
 pub enum SquareError {
     InvalidString(String),
@@ -78,8 +264,8 @@ impl From<Square> for u8 {
 }
 
 impl Square {
-    pub fn from_coords(rank: Rank, file: File) -> Square {
-        Square::new(u8::from(rank) * 8 + u8::from(file))
+    pub const fn from_coords(rank: Rank, file: File) -> Square {
+        Square(rank.0 * 8 + file.0)
     }
 
     pub fn try_from_str(s: &str) -> Result<Square, SquareError> {
@@ -96,13 +282,82 @@ impl Square {
         Err(SquareError::InvalidString(String::from(s)))
     }
 
-    pub fn rank(self) -> Rank {
+    pub const fn rank(self) -> Rank {
         Rank(self.0 / 8)
     }
 
-    pub fn file(self) -> File {
+    pub const fn file(self) -> File {
         File(self.0 % 8)
     }
+
+    // The square as seen from color's side of the board, mirrored top to
+    // bottom for black, so tables can be written once from white's side
+    pub const fn relative_to(self, color: Color) -> Square {
+        match color {
+            Color::White => self,
+            Color::Black => Square(self.0 ^ 56),
+        }
+    }
+
+    pub fn same_rank(self, other: Square) -> bool {
+        self.rank() == other.rank()
+    }
+
+    pub fn same_file(self, other: Square) -> bool {
+        self.file() == other.file()
+    }
+
+    // on a common diagonal, in either direction
+    pub fn same_diagonal(self, other: Square) -> bool {
+        let (rank, file) = (self.rank().0 as i8, self.file().0 as i8);
+        let (other_rank, other_file) = (other.rank().0 as i8, other.file().0 as i8);
+        rank - file == other_rank - other_file || rank + file == other_rank + other_file
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+impl FromStr for Square {
+    type Err = SquareError;
+
+    fn from_str(s: &str) -> Result<Square, SquareError> {
+        Square::try_from_str(s)
+    }
+}
+
+// An array indexed by square, for boards and per-square tables
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BySquare<T>([T; 64]);
+
+impl<T> BySquare<T> {
+    pub const fn new(squares: [T; 64]) -> BySquare<T> {
+        BySquare(squares)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Square, &T)> {
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| (Square::new(idx as u8), value))
+    }
+}
+
+impl<T> Index<Square> for BySquare<T> {
+    type Output = T;
+
+    fn index(&self, sqr: Square) -> &T {
+        &self.0[sqr.0 as usize]
+    }
+}
+
+impl<T> IndexMut<Square> for BySquare<T> {
+    fn index_mut(&mut self, sqr: Square) -> &mut T {
+        &mut self.0[sqr.0 as usize]
+    }
 }
 
 // Direction
@@ -194,40 +449,52 @@ impl Shiftable for Square {
     }
 }
 
-#[repr(u8)]
-#[derive(PartialEq, Debug, Clone, Copy)]
-pub enum Piece {
-    WhitePawn,
-    WhiteKnight,
-    WhiteBishop,
-    WhiteRook,
-    WhiteQueen,
-    WhiteKing,
-    BlackPawn,
-    BlackKnight,
-    BlackBishop,
-    BlackRook,
-    BlackQueen,
-    BlackKing,
-    None,
-}
-
-// impl From<Piece> for char {
-//     fn from(piece: Piece) -> char {
-//         match piece {
-//             Piece::WhitePawn => 'P',
-//             Piece::WhiteKnight => 'N',
-//             Piece::WhiteBishop => 'B',
-//             Piece::WhiteRook => 'R',
-//             Piece::WhiteQueen => 'Q',
-//             Piece::WhiteKing => 'K',
-//             Piece::BlackPawn => 'p',
-//             Piece::BlackKnight => 'n',
-//             Piece::BlackBishop => 'b',
-//             Piece::BlackRook => 'r',
-//             Piece::BlackQueen => 'q',
-//             Piece::BlackKing => 'k',
-//             Piece::None => '.',
-//         }
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_squares() {
+        for (idx, &sqr) in Square::ALL.iter().enumerate() {
+            assert_eq!(u8::from(sqr), idx as u8);
+        }
+        assert_eq!(Square::A1, Square::new(0));
+        assert_eq!(Square::E4, Square::try_from_str("e4").unwrap());
+        assert_eq!(Square::H8, Square::new(63));
+    }
+
+    #[test]
+    fn parse_and_display() {
+        for &sqr in &Square::ALL {
+            assert_eq!(sqr.to_string().parse::<Square>().unwrap(), sqr);
+        }
+        assert_eq!(Square::G7.to_string(), "g7");
+        assert_eq!("3".parse::<Rank>().unwrap(), Rank::new(2));
+        assert_eq!("c".parse::<File>().unwrap(), File::new(2));
+        assert_eq!(Rank::new(7).to_string(), "8");
+        assert_eq!(File::new(7).to_string(), "h");
+        assert!("i1".parse::<Square>().is_err());
+        assert!("9".parse::<Rank>().is_err());
+        assert!("ab".parse::<File>().is_err());
+    }
+
+    #[test]
+    fn rank_and_file_squares() {
+        let rank: Vec<Square> = Rank::new(1).squares().collect();
+        assert_eq!(rank.len(), 8);
+        assert_eq!(rank[0], Square::A2);
+        assert_eq!(rank[7], Square::H2);
+        let file: Vec<Square> = File::new(4).squares().collect();
+        assert_eq!(file[0], Square::E1);
+        assert_eq!(file[7], Square::E8);
+    }
+
+    #[test]
+    fn relative_to_color() {
+        assert_eq!(Square::E2.relative_to(Color::White), Square::E2);
+        assert_eq!(Square::E2.relative_to(Color::Black), Square::E7);
+        assert_eq!(Square::A1.relative_to(Color::Black), Square::A8);
+        assert_eq!(Rank::new(0).relative_to(Color::Black), Rank::new(7));
+        assert_eq!(Rank::new(3).relative_to(Color::White), Rank::new(3));
+    }
+}
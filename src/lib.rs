@@ -1,8 +1,7 @@
-#![macro_use]
-extern crate lazy_static;
-
 mod boardstructs;
 mod bitboard;
 mod piece;
 mod position;
 mod moves;
+#[cfg(feature = "serde")]
+mod serialization;
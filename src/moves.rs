@@ -1,22 +1,40 @@
 mod magic;
+#[cfg(target_arch = "x86_64")]
+mod pext;
+mod verify;
 
 mod moveboards;
-use moveboards::{MoveBoards, MOVEBOARDS};
+use moveboards::MoveBoards;
+pub(crate) use moveboards::MOVEBOARDS;
 
-use crate::boardstructs::{Direction, File, Piece, Rank, Shiftable, Square};
+use crate::boardstructs::{Direction, File, Rank, Shiftable, Square};
+use crate::piece::{Piece, PieceType};
 use crate::position::Position;
 
 use smallvec::SmallVec;
 
+use std::fmt;
+use std::str::FromStr;
+
+// Whether a move is a capture depends on the position it's played in, so
+// moves only hold what UCI notation does: the squares and any promotion
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub struct Move {
     from: Square,
     to: Square,
-    promotion: bool,
-    capture: bool,
+    // the piece a pawn promotes to
+    promotion: Option<PieceType>,
 }
 
 impl Move {
+    pub fn new(from: Square, to: Square, promotion: Option<PieceType>) -> Move {
+        Move {
+            from,
+            to,
+            promotion,
+        }
+    }
+
     pub fn from(&self) -> Square {
         self.from
     }
@@ -24,6 +42,44 @@ impl Move {
     pub fn to(&self) -> Square {
         self.to
     }
+
+    pub fn promotion(&self) -> Option<PieceType> {
+        self.promotion
+    }
+}
+
+// Long algebraic notation as used by UCI, e.g. "e2e4" or "e7e8q"
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from, self.to)?;
+        if let Some(piece_type) = self.promotion {
+            write!(f, "{}", char::from(piece_type))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Move {
+    type Err = MoveError;
+
+    fn from_str(s: &str) -> Result<Move, MoveError> {
+        let invalid = || MoveError::InvalidString(String::from(s));
+        if !s.is_ascii() || (s.len() != 4 && s.len() != 5) {
+            return Err(invalid());
+        }
+        let from = s[0..2].parse().map_err(|_| invalid())?;
+        let to = s[2..4].parse().map_err(|_| invalid())?;
+        let promotion = match s[4..].chars().next() {
+            None => None,
+            Some(c) => match PieceType::try_from_char(c) {
+                Some(piece_type) if piece_type != PieceType::Pawn && piece_type != PieceType::King => {
+                    Some(piece_type)
+                }
+                _ => return Err(invalid()),
+            },
+        };
+        Ok(Move::new(from, to, promotion))
+    }
 }
 
 
@@ -39,9 +95,30 @@ type MoveVec<T> = SmallVec<[T; 256]>;
 #[derive(Debug)]
 pub enum MoveError {
     IllegalMove(PseudolegalMove),
+    InvalidString(String),
 }
 
 pub fn generate_moves(pos: Position) -> MoveVec<Move>{
 
     todo!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn move_strings() {
+        let mv = Move::new(Square::E2, Square::E4, None);
+        assert_eq!(mv.to_string(), "e2e4");
+        assert_eq!("e2e4".parse::<Move>().unwrap(), mv);
+
+        let promotion = Move::new(Square::E7, Square::E8, Some(PieceType::Knight));
+        assert_eq!(promotion.to_string(), "e7e8n");
+        assert_eq!("e7e8n".parse::<Move>().unwrap(), promotion);
+
+        for s in &["", "e2", "e2e9", "e2e4k", "e7e8Q", "e2e4q1", "é2e4"] {
+            assert!(s.parse::<Move>().is_err(), "{}", s);
+        }
+    }
+}
@@ -1,149 +1,422 @@
 use crate::bitboard::Bitboard;
-use crate::boardstructs::{File, Rank, Square};
+use crate::boardstructs::Square;
 
-use super::moveboards::{bishop_move_board_slow, rook_move_board_slow};
+use super::moveboards::SlidingAttacks;
 
-use smallvec::SmallVec;
+// Magic numbers are fixed so that the tables are the same on every build and
+// can be computed at compile time. They were found with the seeded search in
+// the tests below, which can be rerun with `cargo test -- --ignored`.
+#[rustfmt::skip]
+const BISHOP_MAGIC_NUMS: [u64; 64] = [
+    0x1002200101020088, 0x4004010404049080, 0x200404008a020422, 0x000806004c002202,
+    0x8001104110000090, 0x8002080248000202, 0x0000440484408000, 0x0002010082012020,
+    0x0488406912288201, 0x0101600202004110, 0x0810100400842440, 0x0800240410822002,
+    0x0000020210010030, 0x8100008260210008, 0x4404004404044201, 0x2440008084104200,
+    0x88220a08207c0280, 0x4104801001080120, 0x4110020808304010, 0x2518050402400980,
+    0x0002000422010010, 0x0403020080a00100, 0x0244200200822880, 0x0608200200820860,
+    0x81202818c6100400, 0x4081501088100100, 0x2002491448020400, 0x0070040000440008,
+    0x0880820004010400, 0x8490108202c80400, 0x0004004204050c41, 0x800102400c241402,
+    0x1014200a00041013, 0x0008110400300400, 0x0042060900220800, 0x00d2200800010105,
+    0x0c0a0484008a0020, 0x801001010802100c, 0x0919920202440101, 0x06020a0202604050,
+    0x4202083240260821, 0x0118520260221008, 0x00000c4402021001, 0x1010006018020100,
+    0x9600204c10130500, 0x00c0810403000020, 0x0002640420801401, 0x8001040400900840,
+    0x1002221004048000, 0x0020808808420401, 0x0100010080908040, 0x2000800884040820,
+    0x1000002020444b28, 0x01180890d0008888, 0x4090448800940100, 0x0160380a00404240,
+    0x5002042109101080, 0x800800220d300800, 0x0808200840441004, 0x8008022006150402,
+    0x2000000070020213, 0x0202414119211100, 0x8820620204180484, 0x8012100115010208,
+];
 
-pub struct SquareMagic {
-    table: [Bitboard; 4096],
-    mask: Bitboard,
+#[rustfmt::skip]
+const ROOK_MAGIC_NUMS: [u64; 64] = [
+    0x2080002080400010, 0x00c0002001401000, 0x2100110008402002, 0x0880080081041000,
+    0x0200020020041008, 0x2300040008010012, 0x0c00283004008201, 0x0180010000407a80,
+    0x0168800080400020, 0x0010400040201000, 0x1001002001001048, 0x1001002408100100,
+    0x0801000408010012, 0x4001000209000400, 0x08a20004c8020001, 0x2002801145002280,
+    0x0080860021004200, 0x001000c009402002, 0x00b0002004002800, 0x100a808010020800,
+    0x8101010008000410, 0x0244008002000480, 0x0000040010810208, 0x2000020000448534,
+    0x4104400480008033, 0x0000810100204000, 0x0440430900200010, 0x4600240900100100,
+    0x0060080080040080, 0x0001000300080400, 0x0004084400011002, 0x0023040200008041,
+    0x0580050043002080, 0x0400804002802008, 0x0001002001004010, 0x1000200901001000,
+    0x4410800801800c00, 0xa012003806001004, 0x0020100104008802, 0x0004808402000041,
+    0x0010400170898000, 0x0080500020004004, 0x1040408012020020, 0x8010040008004040,
+    0x2001080100110004, 0x0000020004008080, 0x0021010810040002, 0x0800008c43020024,
+    0x0000800021005100, 0x0070201040008080, 0x0000d04282006a00, 0x0010014400080240,
+    0x0001080110050100, 0x0012000810240600, 0x0402000801040200, 0x028100108a004100,
+    0x0050800300102045, 0x8208210040120882, 0x8010600101183441, 0x020b000910006045,
+    0x0241001002480005, 0x0081000400880241, 0x0000009008024124, 0x0048122980410402,
+];
+
+// (rank, file) steps for each sliding direction
+pub(super) const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+pub(super) const ROOK_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+// One square's slice of a shared move table. The PEXT backend uses the same
+// layout, without the magic number and shift.
+#[derive(Clone, Copy)]
+pub(super) struct SquareMagic {
+    // where this square's moves start in the shared table
+    pub(super) offset: usize,
+    pub(super) mask: Bitboard,
     magic_num: u64,
     shift: u8,
 }
 
 impl SquareMagic {
-    pub fn lookup(&self, blockers: Bitboard) -> Bitboard {
+    const EMPTY: SquareMagic = SquareMagic {
+        offset: 0,
+        mask: Bitboard::new(),
+        magic_num: 0,
+        shift: 0,
+    };
+
+    fn index(&self, blockers: Bitboard) -> usize {
         let relevant_blockers = blockers & self.mask;
-        let idx = magic_hash(relevant_blockers, self.magic_num, self.shift);
-        self.table[idx as usize]
+        self.offset + magic_hash(u64::from(relevant_blockers), self.magic_num, self.shift)
     }
 }
 
-// TODO: this is expensive, there's probably a better way
-fn create_sqr_array<T, F>(f: F) -> [T; 64]
-where
-    F: FnMut(Square) -> T,
-{
-    (0..64)
-        .map(|i| Square::new(i))
-        .map(f)
-        .collect::<SmallVec<[T; 64]>>()
-        .into_inner()
-        .unwrap_or_else(|v| {
-            panic!(
-                "Expected a vector of size 64, instead got one of size {}.\
-             This state is (hopefully) unreachable, but the compiler doesn't know that.",
-                v.len()
-            )
-        })
+// Each square only needs 2^(bits in its mask) entries, so rather than giving
+// every square a 4096 entry table we pack them all into one ("fancy" magics),
+// see https://www.chessprogramming.org/Magic_Bitboards#Fancy
+pub(super) const TABLE_SIZE: usize = table_size(&BISHOP_DIRECTIONS) + table_size(&ROOK_DIRECTIONS);
+
+pub struct SlidingMagics {
+    bishop_magics: [SquareMagic; 64],
+    rook_magics: [SquareMagic; 64],
+    table: [Bitboard; TABLE_SIZE],
 }
 
-fn bitboard_combinations(bb: Bitboard) -> Vec<Bitboard> {
-    let mut combinations = Vec::<Bitboard>::new();
-    let squares = bb.into_iter().collect::<Vec<Square>>();
-    for combo_idx in 0..(u64::pow(2, bb.len().into())) {
-        let mut bb = Bitboard::new();
-        // not actually a bitboard representing a board, we use it instead to
-        // index a combination
-        let combo_bb = Bitboard::from(combo_idx);
-        for i in 0..squares.len() {
-            if combo_bb.contains(Square::new(i as u8)) {
-                bb.insert(squares[i as usize]);
-            }
+impl SlidingMagics {
+    pub const fn new() -> SlidingMagics {
+        let mut table = [Bitboard::new(); TABLE_SIZE];
+        let (bishop_magics, offset) =
+            gen_magics(&BISHOP_DIRECTIONS, Some(&BISHOP_MAGIC_NUMS), &mut table, 0);
+        let (rook_magics, _) =
+            gen_magics(&ROOK_DIRECTIONS, Some(&ROOK_MAGIC_NUMS), &mut table, offset);
+        SlidingMagics {
+            bishop_magics,
+            rook_magics,
+            table,
         }
-        combinations.push(bb);
     }
-    combinations
 }
 
-pub fn gen_bishop_magics() -> [SquareMagic; 64] {
-    create_sqr_array(gen_bishop_magic_for_sqr)
+impl SlidingAttacks for SlidingMagics {
+    fn bishop_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+        self.table[self.bishop_magics[u8::from(sqr) as usize].index(blockers)]
+    }
+
+    fn rook_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+        self.table[self.rook_magics[u8::from(sqr) as usize].index(blockers)]
+    }
 }
 
-fn gen_bishop_magic_for_sqr(sqr: Square) -> SquareMagic {
-    let mask = {
-        bishop_move_board_slow(sqr, Bitboard::new())
-            & Bitboard::from(Rank::new(0)).invert()
-            & Bitboard::from(Rank::new(7)).invert()
-            & Bitboard::from(File::new(0)).invert()
-            & Bitboard::from(File::new(7)).invert()
-    };
-    let combinations = bitboard_combinations(mask);
-    let moves = combinations
-        .iter()
-        .map(|&blockers| bishop_move_board_slow(sqr, blockers))
-        .collect::<Vec<Bitboard>>();
-    gen_square_magic(&combinations, &moves)
+// Walks each direction from sqr until it leaves the board or hits a blocker,
+// which is included in the result. Works on raw u64s so it can run in const
+// contexts.
+pub(super) const fn sliding_moves(sqr: u8, blockers: u64, directions: &[(i8, i8); 4]) -> u64 {
+    let mut moves = 0;
+    let mut i = 0;
+    while i < 4 {
+        let (dr, df) = directions[i];
+        let mut rank = (sqr / 8) as i8 + dr;
+        let mut file = (sqr % 8) as i8 + df;
+        while 0 <= rank && rank < 8 && 0 <= file && file < 8 {
+            let bit = 1 << (rank * 8 + file);
+            moves |= bit;
+            if blockers & bit != 0 {
+                break;
+            }
+            rank += dr;
+            file += df;
+        }
+        i += 1;
+    }
+    moves
 }
 
-pub fn gen_rook_magics() -> [SquareMagic; 64] {
-    create_sqr_array(gen_rook_magic_for_sqr)
+// The squares whose occupancy can change the moves from sqr. The last square
+// of each ray never matters, since a piece there doesn't block anything.
+pub(super) const fn sliding_mask(sqr: u8, directions: &[(i8, i8); 4]) -> u64 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < 4 {
+        let (dr, df) = directions[i];
+        let mut rank = (sqr / 8) as i8 + dr;
+        let mut file = (sqr % 8) as i8 + df;
+        while 0 <= rank + dr && rank + dr < 8 && 0 <= file + df && file + df < 8 {
+            mask |= 1 << (rank * 8 + file);
+            rank += dr;
+            file += df;
+        }
+        i += 1;
+    }
+    mask
 }
 
-fn gen_rook_magic_for_sqr(sqr: Square) -> SquareMagic {
-    let mask = {
-        rook_move_board_slow(sqr, Bitboard::new())
-            & Bitboard::from(Rank::new(0)).invert()
-            & Bitboard::from(Rank::new(7)).invert()
-            & Bitboard::from(File::new(0)).invert()
-            & Bitboard::from(File::new(7)).invert()
-    };
-    let combinations = bitboard_combinations(mask);
-    let moves = combinations
-        .iter()
-        .map(|&blockers| rook_move_board_slow(sqr, blockers))
-        .collect::<Vec<Bitboard>>();
-    gen_square_magic(&combinations, &moves)
+const fn table_size(directions: &[(i8, i8); 4]) -> usize {
+    let mut size = 0;
+    let mut sqr = 0;
+    while sqr < 64 {
+        size += 1 << sliding_mask(sqr, directions).count_ones();
+        sqr += 1;
+    }
+    size
 }
 
-fn magic_hash(key: Bitboard, magic: u64, shift: u8) -> u64 {
-    (u64::from(key) * magic) << shift
+// Fills in the moves for every square from offset onwards, returning the
+// magics and the offset just past the last entry written. Without magic
+// numbers each square's moves are stored in the order PEXT indexes them.
+pub(super) const fn gen_magics(
+    directions: &[(i8, i8); 4],
+    magic_nums: Option<&[u64; 64]>,
+    table: &mut [Bitboard; TABLE_SIZE],
+    mut offset: usize,
+) -> ([SquareMagic; 64], usize) {
+    let mut magics = [SquareMagic::EMPTY; 64];
+    let mut sqr = 0;
+    while sqr < 64 {
+        let mask = sliding_mask(sqr as u8, directions);
+        let magic = SquareMagic {
+            offset,
+            mask: Bitboard::from_u64(mask),
+            magic_num: match magic_nums {
+                Some(magic_nums) => magic_nums[sqr],
+                None => 0,
+            },
+            shift: 64 - mask.count_ones() as u8,
+        };
+        // Enumerate every subset of mask with the Carry-Rippler trick, see
+        // https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
+        // It visits the subsets in increasing order, which is exactly the
+        // order of their PEXT indices.
+        let mut blockers: u64 = 0;
+        let mut subset = 0;
+        loop {
+            let idx = match magic_nums {
+                Some(_) => offset + magic_hash(blockers, magic.magic_num, magic.shift),
+                None => offset + subset,
+            };
+            table[idx] = Bitboard::from_u64(sliding_moves(sqr as u8, blockers, directions));
+            subset += 1;
+            blockers = blockers.wrapping_sub(mask) & mask;
+            if blockers == 0 {
+                break;
+            }
+        }
+        magics[sqr] = magic;
+        offset += 1 << mask.count_ones();
+        sqr += 1;
+    }
+    (magics, offset)
 }
 
-fn magic_pushes_up(magic: u64, mask: Bitboard) -> bool {
-    let prod = magic * u64::from(mask);
-    let num_high_bits = prod & 0xff00000000000000;
-    num_high_bits > 6
+const fn magic_hash(key: u64, magic: u64, shift: u8) -> usize {
+    (key.wrapping_mul(magic) >> shift) as usize
 }
 
-fn gen_square_magic(possible_blockers: &[Bitboard], possible_moves: &[Bitboard]) -> SquareMagic {
-    let mask = possible_moves[0];
-    let shift = 64 - mask.len();
-    // The maximum size that a hash key can be is 12, for a corner rook move.
-    // Therefore our table only needs to be 4096 = 2^12 large
-    let mut table = [Bitboard::new(); 4096];
-    let mut magic: Option<u64> = None;
-    while let None = magic {
-        // zero out table
-        for bb in table.iter_mut() {
-            *bb = Bitboard::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::moves::verify::blocker_subsets;
+
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    const BISHOP_SEED: u64 = 0x2545f4914f6cdd1d;
+    const ROOK_SEED: u64 = 0x9e3779b97f4a7c15;
+
+    // The table is too big for a test thread's stack
+    static SLIDING_MAGICS: SlidingMagics = SlidingMagics::new();
+
+    fn xorshift(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    fn find_magic_num(sqr: u8, directions: &[(i8, i8); 4], rng: &mut u64) -> u64 {
+        let mask = sliding_mask(sqr, directions);
+        let shift = 64 - mask.count_ones() as u8;
+        let possible_blockers: Vec<(u64, u64)> = blocker_subsets(Bitboard::from(mask))
+            .into_iter()
+            .map(|blockers| {
+                let blockers = u64::from(blockers);
+                (blockers, sliding_moves(sqr, blockers, directions))
+            })
+            .collect();
+        let mut table = vec![0; 1 << mask.count_ones()];
+        'search: loop {
+            // Use & thrice to get a random value with a low number of non-zero bits
+            let magic = xorshift(rng) & xorshift(rng) & xorshift(rng);
+            // We want our magic number to "push up" the bits of mask so that
+            // the top byte has many non-zero bits
+            if (mask.wrapping_mul(magic) & 0xff00000000000000).count_ones() < 6 {
+                continue;
+            }
+            for entry in table.iter_mut() {
+                *entry = 0;
+            }
+            for &(blockers, moves) in &possible_blockers {
+                let idx = magic_hash(blockers, magic, shift);
+                if table[idx] == 0 {
+                    table[idx] = moves;
+                } else if table[idx] != moves {
+                    // A bad collision, two blocker bitboards with different
+                    // moves share an index
+                    continue 'search;
+                }
+            }
+            return magic;
+        }
+    }
+
+    fn check_magics<F>(directions: &[(i8, i8); 4], lookup: F)
+    where
+        F: Fn(Square, Bitboard) -> Bitboard,
+    {
+        for sqr in 0..64 {
+            let mask = Bitboard::from(sliding_mask(sqr, directions));
+            for blockers in blocker_subsets(mask) {
+                assert_eq!(
+                    u64::from(lookup(Square::new(sqr), blockers)),
+                    sliding_moves(sqr, u64::from(blockers), directions)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn table_size() {
+        assert_eq!(TABLE_SIZE, 5248 + 102400);
+    }
+
+    #[test]
+    fn bishop_magics() {
+        check_magics(&BISHOP_DIRECTIONS, |sqr, blockers| {
+            SLIDING_MAGICS.bishop_moves(sqr, blockers)
+        });
+    }
+
+    #[test]
+    fn rook_magics() {
+        check_magics(&ROOK_DIRECTIONS, |sqr, blockers| {
+            SLIDING_MAGICS.rook_moves(sqr, blockers)
+        });
+    }
+
+    // The layout fancy magics replaced: the same magics, but every square
+    // gets 4096 entries, the most any rook needs
+    struct UnpackedMagics {
+        bishop_magics: [SquareMagic; 64],
+        rook_magics: [SquareMagic; 64],
+        table: Vec<Bitboard>,
+    }
+
+    impl UnpackedMagics {
+        fn new() -> UnpackedMagics {
+            let mut table = vec![Bitboard::new(); 2 * 64 * 4096];
+            let mut unpack = |magics: &[SquareMagic; 64], first_offset: usize| {
+                let mut unpacked = *magics;
+                for (sqr, magic) in unpacked.iter_mut().enumerate() {
+                    magic.offset = first_offset + sqr * 4096;
+                    let size = 1 << magic.mask.len();
+                    for i in 0..size {
+                        table[magic.offset + i] = SLIDING_MAGICS.table[magics[sqr].offset + i];
+                    }
+                }
+                unpacked
+            };
+            let bishop_magics = unpack(&SLIDING_MAGICS.bishop_magics, 0);
+            let rook_magics = unpack(&SLIDING_MAGICS.rook_magics, 64 * 4096);
+            UnpackedMagics {
+                bishop_magics,
+                rook_magics,
+                table,
+            }
+        }
+    }
+
+    impl SlidingAttacks for UnpackedMagics {
+        fn bishop_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+            self.table[self.bishop_magics[u8::from(sqr) as usize].index(blockers)]
         }
-        // Use & thrice to get a random value with a low number of non-zero bits
-        let magic_guess = rand::random::<u64>() & rand::random::<u64>() & rand::random::<u64>();
-        magic = Some(magic_guess);
-        // We want our magic number to "push up" the bits of mask so that the top byte has many non-zero bits
-        if !magic_pushes_up(magic_guess, mask) {
-            continue;
+
+        fn rook_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+            self.table[self.rook_magics[u8::from(sqr) as usize].index(blockers)]
         }
-        for (blockers, moves) in possible_blockers.iter().zip(possible_moves) {
-            let idx = magic_hash(*blockers, magic_guess, shift) as usize;
-            if table[idx] == Bitboard::new() {
-                table[idx] = *moves;
-            } else if table[idx] == *moves {
-                // Do nothing, this is a good hash collision where two blocker
-                // bitboards share the same resultant move bitboard and also
-                // happen to map to the same index
-            } else {
-                magic = None;
-                break;
+    }
+
+    // Queen moves from every occupied square, which is the lookup pattern of
+    // sliding move generation, over many boards so that lookups land all
+    // over the table the way they do in a search. Returns the best ns per
+    // lookup over a few runs, and every move XORed together so that backends
+    // can be checked against each other.
+    fn time_lookups<S: SlidingAttacks>(
+        sliding_attacks: &S,
+        boards: &[Bitboard],
+    ) -> (f64, Bitboard) {
+        let mut best = f64::INFINITY;
+        let mut moves = Bitboard::new();
+        for _ in 0..5 {
+            let mut lookups = 0;
+            moves = Bitboard::new();
+            let start = Instant::now();
+            for &occupied in boards {
+                for sqr in occupied {
+                    moves ^= sliding_attacks.bishop_moves(sqr, occupied)
+                        ^ sliding_attacks.rook_moves(sqr, occupied);
+                    lookups += 2;
+                }
             }
+            black_box(moves);
+            let ns = start.elapsed().as_nanos() as f64 / lookups as f64;
+            best = best.min(ns);
         }
+        (best, moves)
+    }
+
+    // Compares fancy magics against the unpacked layout they replaced. Run
+    // with `cargo test --release -- --ignored --nocapture movegen_benchmark`.
+    #[test]
+    #[ignore]
+    fn movegen_benchmark() {
+        let mut rng = BISHOP_SEED;
+        // about a quarter of the squares occupied, like a middlegame
+        let boards: Vec<Bitboard> = (0..1_000_000)
+            .map(|_| Bitboard::from(xorshift(&mut rng) & xorshift(&mut rng)))
+            .collect();
+        let unpacked = UnpackedMagics::new();
+        let kib = |entries: usize| entries * std::mem::size_of::<Bitboard>() / 1024;
+        let (fancy_ns, fancy_moves) = time_lookups(&SLIDING_MAGICS, &boards);
+        let (unpacked_ns, unpacked_moves) = time_lookups(&unpacked, &boards);
+        assert_eq!(fancy_moves, unpacked_moves);
+        println!(
+            "fancy magics:    {:>5} KiB, {:.2} ns/lookup",
+            kib(TABLE_SIZE),
+            fancy_ns
+        );
+        println!(
+            "unpacked magics: {:>5} KiB, {:.2} ns/lookup",
+            kib(unpacked.table.len()),
+            unpacked_ns
+        );
     }
-    SquareMagic {
-        table,
-        mask,
-        magic_num: magic.unwrap(),
-        shift,
+
+    #[test]
+    #[ignore]
+    fn magic_nums_match_search() {
+        let mut rng = BISHOP_SEED;
+        for sqr in 0..64 {
+            let magic = find_magic_num(sqr, &BISHOP_DIRECTIONS, &mut rng);
+            assert_eq!(magic, BISHOP_MAGIC_NUMS[sqr as usize]);
+        }
+        let mut rng = ROOK_SEED;
+        for sqr in 0..64 {
+            let magic = find_magic_num(sqr, &ROOK_DIRECTIONS, &mut rng);
+            assert_eq!(magic, ROOK_MAGIC_NUMS[sqr as usize]);
+        }
     }
 }
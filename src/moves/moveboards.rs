@@ -1,12 +1,14 @@
 use crate::bitboard::Bitboard;
 
-use crate::boardstructs::{Direction, Piece, Shiftable, Square};
+use crate::boardstructs::{Direction, Shiftable, Square};
+use crate::piece::{Piece, PieceType};
 
-use super::magic::{SquareMagic, gen_bishop_magics, gen_rook_magics};
+use super::magic::{sliding_moves, SlidingMagics, BISHOP_DIRECTIONS, ROOK_DIRECTIONS};
+#[cfg(target_arch = "x86_64")]
+use super::pext::{fast_pext, run_with_pext};
 
-use lazy_static::lazy_static;
-
-// slow iterative generation of moveboards, only called during the initialization to generate magic bitboards
+// slow iterative generation of moveboards, used as a reference to check the
+// sliding move tables against, see verify.rs
 pub fn bishop_move_board_slow(sqr: Square, blockers: Bitboard) -> Bitboard {
     let mut moves = Bitboard::new();
     for direction in &[
@@ -21,7 +23,7 @@ pub fn bishop_move_board_slow(sqr: Square, blockers: Bitboard) -> Bitboard {
             if blockers.contains(s) {
                 break;
             }
-            destination = sqr.shift(*direction);
+            destination = s.shift(*direction);
         }
     }
     moves
@@ -41,12 +43,42 @@ pub fn rook_move_board_slow(sqr: Square, blockers: Bitboard) -> Bitboard {
             if blockers.contains(s) {
                 break;
             }
-            destination = sqr.shift(*direction);
+            destination = s.shift(*direction);
         }
     }
     moves
 }
 
+// Lookups for the moves of sliding pieces. There is more than one way to do
+// these quickly, and which is fastest depends on the CPU.
+pub trait SlidingAttacks {
+    fn bishop_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard;
+    fn rook_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard;
+}
+
+// Work that does sliding lookups, such as move generation. It's generic over
+// the backend so that each backend gets its own copy with the lookups
+// inlined, see with_sliding_attacks.
+pub trait WithSlidingAttacks {
+    type Output;
+    fn run<S: SlidingAttacks>(self, sliding_attacks: &S) -> Self::Output;
+}
+
+// Runs work with the fastest backend for this CPU: PEXT where it's fast,
+// magics otherwise. Move generation and search should enter through here, so
+// that the backend is checked once per call rather than once per lookup, and
+// so that PEXT lookups can be inlined.
+pub fn with_sliding_attacks<W: WithSlidingAttacks>(work: W) -> W::Output {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if let Some(pext) = fast_pext() {
+            // SAFETY: a SlidingPext only exists on CPUs with BMI2
+            return unsafe { run_with_pext(work, pext) };
+        }
+    }
+    work.run(&MOVEBOARDS.sliding_magics)
+}
+
 // This struct stores bitboards that map from a square to all the squares a
 // piece on that square threatens, assuming an empty board
 pub struct MoveBoards {
@@ -54,9 +86,14 @@ pub struct MoveBoards {
     king_moves: [Bitboard; 64],
     knight_moves: [Bitboard; 64],
 
-    // magics for sliding move generation
-    bishop_magics: [SquareMagic; 64],
-    rook_magics: [SquareMagic; 64],
+    // the sliding backend every CPU can use, see move_board
+    sliding_magics: SlidingMagics,
+
+    // geometry between pairs of squares, indexed [a][b]
+    between: [[Bitboard; 64]; 64],
+    line: [[Bitboard; 64]; 64],
+    chebyshev_distance: [[u8; 64]; 64],
+    manhattan_distance: [[u8; 64]; 64],
     // threat boards for sliding pieces
     // not actually used for move generation
     // bishop_threats: [Bitboard; 64],
@@ -65,104 +102,208 @@ pub struct MoveBoards {
 
 // initialization
 impl MoveBoards {
-    fn gen_king_moves() -> [Bitboard; 64] {
+    // Moves for pieces that jump straight to a fixed set of (rank, file)
+    // offsets, which is every non-sliding piece except pawns
+    const fn gen_leaper_moves(offsets: &[(i8, i8); 8]) -> [Bitboard; 64] {
         let mut boards = [Bitboard::new(); 64];
-        for sqr_idx in 0..64 {
-            let sqr = Square::new(sqr_idx);
-            for direction in &[
-                Direction::Up,
-                Direction::UpLeft,
-                Direction::Left,
-                Direction::DownLeft,
-                Direction::Down,
-                Direction::DownRight,
-                Direction::Right,
-                Direction::UpRight,
-            ] {
-                if let Some(s) = sqr.shift(*direction) {
-                    boards[sqr_idx as usize].insert(s);
+        let mut sqr_idx = 0;
+        while sqr_idx < 64 {
+            let mut moves = 0;
+            let mut i = 0;
+            while i < 8 {
+                let rank = (sqr_idx / 8) as i8 + offsets[i].0;
+                let file = (sqr_idx % 8) as i8 + offsets[i].1;
+                if 0 <= rank && rank < 8 && 0 <= file && file < 8 {
+                    moves |= 1 << (rank * 8 + file);
                 }
+                i += 1;
             }
+            boards[sqr_idx] = Bitboard::from_u64(moves);
+            sqr_idx += 1;
         }
         boards
     }
 
-    fn gen_knight_moves() -> [Bitboard; 64] {
-        let mut boards = [Bitboard::new(); 64];
-        for sqr_idx in 0..64 {
-            let sqr = Square::new(sqr_idx);
-            for [d1, d2, d3] in &[
-                [Direction::Up, Direction::Up, Direction::Right],
-                [Direction::Right, Direction::Right, Direction::Up],
-                [Direction::Right, Direction::Right, Direction::Down],
-                [Direction::Down, Direction::Down, Direction::Right],
-                [Direction::Down, Direction::Down, Direction::Left],
-                [Direction::Left, Direction::Left, Direction::Down],
-                [Direction::Left, Direction::Left, Direction::Up],
-                [Direction::Up, Direction::Up, Direction::Left],
-            ] {
-                // TODO: Look at some map-ish way of doing this. Map doesn't work bc
-                // Option<Square>.map(|s| s.shift(d)) :: Option<Option<Square>>
-                let mut destination = sqr.shift(*d1);
-                if let Some(s) = destination {
-                    destination = s.shift(*d2);
-                }
-                if let Some(s) = destination {
-                    destination = s.shift(*d3);
-                }
-                if let Some(s) = destination {
-                    boards[sqr_idx as usize].insert(s);
+    const fn gen_king_moves() -> [Bitboard; 64] {
+        MoveBoards::gen_leaper_moves(&[
+            (1, 0),
+            (1, -1),
+            (0, -1),
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ])
+    }
+
+    const fn gen_knight_moves() -> [Bitboard; 64] {
+        MoveBoards::gen_leaper_moves(&[
+            (2, 1),
+            (1, 2),
+            (-1, 2),
+            (-2, 1),
+            (-2, -1),
+            (-1, -2),
+            (1, -2),
+            (2, -1),
+        ])
+    }
+
+    // Squares strictly between each pair of squares, and the whole line
+    // through them from edge to edge. Both are empty unless the squares share
+    // a rank, file or diagonal.
+    const fn gen_between_and_line() -> ([[Bitboard; 64]; 64], [[Bitboard; 64]; 64]) {
+        let mut between = [[Bitboard::new(); 64]; 64];
+        let mut line = [[Bitboard::new(); 64]; 64];
+        let mut a = 0;
+        while a < 64 {
+            let mut b = 0;
+            while b < 64 {
+                let (rank_a, file_a) = ((a / 8) as i8, (a % 8) as i8);
+                let (rank_b, file_b) = ((b / 8) as i8, (b % 8) as i8);
+                let directions = if a == b {
+                    None
+                } else if rank_a == rank_b || file_a == file_b {
+                    Some(&ROOK_DIRECTIONS)
+                } else if rank_a - file_a == rank_b - file_b || rank_a + file_a == rank_b + file_b {
+                    Some(&BISHOP_DIRECTIONS)
+                } else {
+                    None
+                };
+                if let Some(directions) = directions {
+                    // The rays from a and b only overlap on the line they share
+                    let (a_bb, b_bb) = (1 << a, 1 << b);
+                    between[a][b] = Bitboard::from_u64(
+                        sliding_moves(a as u8, b_bb, directions)
+                            & sliding_moves(b as u8, a_bb, directions),
+                    );
+                    line[a][b] = Bitboard::from_u64(
+                        (sliding_moves(a as u8, 0, directions)
+                            & sliding_moves(b as u8, 0, directions))
+                            | a_bb
+                            | b_bb,
+                    );
                 }
+                b += 1;
             }
+            a += 1;
         }
-        boards
+        (between, line)
+    }
+
+    const fn gen_distances() -> ([[u8; 64]; 64], [[u8; 64]; 64]) {
+        let mut chebyshev = [[0; 64]; 64];
+        let mut manhattan = [[0; 64]; 64];
+        let mut a = 0;
+        while a < 64 {
+            let mut b = 0;
+            while b < 64 {
+                let rank_distance = ((a / 8) as i8 - (b / 8) as i8).unsigned_abs();
+                let file_distance = ((a % 8) as i8 - (b % 8) as i8).unsigned_abs();
+                chebyshev[a][b] = if rank_distance > file_distance {
+                    rank_distance
+                } else {
+                    file_distance
+                };
+                manhattan[a][b] = rank_distance + file_distance;
+                b += 1;
+            }
+            a += 1;
+        }
+        (chebyshev, manhattan)
     }
 
-    // NOTE: This function is only intended to be called once in a program's execution, you don't need multiple
-    // TODO: make this constant
-    fn new() -> MoveBoards {
+    // NOTE: This is evaluated at compile time to build MOVEBOARDS, there is no
+    //       need to call it anywhere else
+    const fn new() -> MoveBoards {
         let king_moves = MoveBoards::gen_king_moves();
         let knight_moves = MoveBoards::gen_knight_moves();
-        let bishop_magics = gen_bishop_magics();
-        let rook_magics = gen_rook_magics();
+        let sliding_magics = SlidingMagics::new();
+        let (between, line) = MoveBoards::gen_between_and_line();
+        let (chebyshev_distance, manhattan_distance) = MoveBoards::gen_distances();
 
         MoveBoards {
             king_moves,
             knight_moves,
-            bishop_magics,
-            rook_magics,
+            sliding_magics,
+            between,
+            line,
+            chebyshev_distance,
+            manhattan_distance,
         }
     }
 
+    // Moves with sliding lookups from the backend picked for this CPU, PEXT
+    // where it's fast and magics otherwise. fast_pext makes that choice once
+    // and caches it, so this only costs a load per call. Hot loops should
+    // still run under with_sliding_attacks and use move_board_with, where
+    // the PEXT lookups can be inlined.
     // TODO: look into a version of this without blockers
     pub fn move_board(&self, sqr: Square, piece: Piece, blockers: Bitboard) -> Bitboard {
-        match piece {
-            Piece::None => {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if let Some(pext) = fast_pext() {
+                return self.move_board_with(pext, sqr, piece, blockers);
+            }
+        }
+        self.move_board_with(&self.sliding_magics, sqr, piece, blockers)
+    }
+
+    #[inline]
+    pub fn move_board_with<S: SlidingAttacks>(
+        &self,
+        sliding_attacks: &S,
+        sqr: Square,
+        piece: Piece,
+        blockers: Bitboard,
+    ) -> Bitboard {
+        match piece.piece_type() {
+            None => {
                 panic!("Can't use move_board on None piece")
             }
-            Piece::WhitePawn | Piece::BlackPawn => {
+            Some(PieceType::Pawn) => {
                 panic!("Can't use move_board to get pawn moves, use TODO method instead")
             }
-            Piece::WhiteKing | Piece::BlackKing => self.king_moves[u8::from(sqr) as usize],
-            Piece::WhiteKnight | Piece::BlackKnight => self.knight_moves[u8::from(sqr) as usize],
-            Piece::WhiteBishop | Piece::BlackBishop => {
-                self.bishop_magics[u8::from(sqr) as usize].lookup(blockers)
-            }
-            Piece::WhiteRook | Piece::BlackRook => {
-                self.rook_magics[u8::from(sqr) as usize].lookup(blockers)
-            }
-            Piece::WhiteQueen | Piece::BlackQueen => {
-                self.bishop_magics[u8::from(sqr) as usize].lookup(blockers)
-                    | self.rook_magics[u8::from(sqr) as usize].lookup(blockers)
+            Some(PieceType::King) => self.king_moves[u8::from(sqr) as usize],
+            Some(PieceType::Knight) => self.knight_moves[u8::from(sqr) as usize],
+            Some(PieceType::Bishop) => sliding_attacks.bishop_moves(sqr, blockers),
+            Some(PieceType::Rook) => sliding_attacks.rook_moves(sqr, blockers),
+            Some(PieceType::Queen) => {
+                sliding_attacks.bishop_moves(sqr, blockers)
+                    | sliding_attacks.rook_moves(sqr, blockers)
             }
         }
     }
 }
 
-lazy_static! {
-    pub static ref MOVEBOARDS: MoveBoards = MoveBoards::new();
+// geometry
+impl MoveBoards {
+    // Squares strictly between a and b, or empty if they don't share a rank,
+    // file or diagonal
+    pub fn between(&self, a: Square, b: Square) -> Bitboard {
+        self.between[u8::from(a) as usize][u8::from(b) as usize]
+    }
+
+    // The whole rank, file or diagonal through a and b, or empty if there
+    // isn't one
+    pub fn line(&self, a: Square, b: Square) -> Bitboard {
+        self.line[u8::from(a) as usize][u8::from(b) as usize]
+    }
+
+    // The number of king moves from a to b
+    pub fn chebyshev_distance(&self, a: Square, b: Square) -> u8 {
+        self.chebyshev_distance[u8::from(a) as usize][u8::from(b) as usize]
+    }
+
+    // The number of single steps up, down, left or right from a to b
+    pub fn manhattan_distance(&self, a: Square, b: Square) -> u8 {
+        self.manhattan_distance[u8::from(a) as usize][u8::from(b) as usize]
+    }
 }
 
+pub static MOVEBOARDS: MoveBoards = MoveBoards::new();
+
 // impl Position {
 //     pub fn moves(&self) -> PrettySmallVec<Move> {
 //         let pseudolegal_moves = self.pseudolegal_moves();
@@ -224,3 +365,214 @@ lazy_static! {
 //         _ => panic!("Don't use MoveBoards for pawn moves, pawns are weird."),
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::moves::magic::sliding_mask;
+    #[cfg(target_arch = "x86_64")]
+    use crate::moves::pext::SlidingPext;
+    use crate::moves::verify::{blocker_subsets, verify_sliding_attacks, Mismatch};
+
+    #[test]
+    fn slow_move_boards() {
+        let a1 = Square::try_from_str("a1").unwrap();
+        let d4 = Square::try_from_str("d4").unwrap();
+        let d6 = Square::try_from_str("d6").unwrap();
+        let f6 = Square::try_from_str("f6").unwrap();
+        assert_eq!(rook_move_board_slow(a1, Bitboard::new()).len(), 14);
+        assert_eq!(bishop_move_board_slow(a1, Bitboard::new()).len(), 7);
+        assert_eq!(bishop_move_board_slow(d4, Bitboard::new()).len(), 13);
+        assert_eq!(rook_move_board_slow(d4, Bitboard::from(d6)).len(), 12);
+        assert_eq!(bishop_move_board_slow(d4, Bitboard::from(f6)).len(), 11);
+    }
+
+    const DIRECTIONS: [(Direction, Direction); 4] = [
+        (Direction::Up, Direction::Down),
+        (Direction::Right, Direction::Left),
+        (Direction::UpRight, Direction::DownLeft),
+        (Direction::UpLeft, Direction::DownRight),
+    ];
+
+    // Every square from sqr towards the edge in direction, nearest first
+    fn ray(sqr: Square, direction: Direction) -> Vec<Square> {
+        let mut squares = Vec::new();
+        let mut destination = sqr.shift(direction);
+        while let Some(s) = destination {
+            squares.push(s);
+            destination = s.shift(direction);
+        }
+        squares
+    }
+
+    #[test]
+    fn between_and_line() {
+        let to_bitboard = |squares: &[Square]| {
+            let mut bb = Bitboard::new();
+            for &s in squares {
+                bb.insert(s);
+            }
+            bb
+        };
+        for a_idx in 0..64 {
+            for b_idx in 0..64 {
+                let (a, b) = (Square::new(a_idx), Square::new(b_idx));
+                let mut between = Bitboard::new();
+                let mut line = Bitboard::new();
+                for &(forward, backward) in &DIRECTIONS {
+                    let (ahead, behind) = (ray(a, forward), ray(a, backward));
+                    for ray in &[&ahead, &behind] {
+                        if let Some(pos) = ray.iter().position(|&s| s == b) {
+                            between = to_bitboard(&ray[..pos]);
+                            line = Bitboard::from(a) | to_bitboard(&ahead) | to_bitboard(&behind);
+                        }
+                    }
+                }
+                assert_eq!(MOVEBOARDS.between(a, b), between);
+                assert_eq!(MOVEBOARDS.line(a, b), line);
+            }
+        }
+    }
+
+    #[test]
+    fn distances() {
+        for a_idx in 0..64 {
+            for b_idx in 0..64 {
+                let (a, b) = (Square::new(a_idx), Square::new(b_idx));
+                // breadth first search over king and rook steps
+                let mut king_reach = Bitboard::from(a);
+                let mut king_steps = 0;
+                while !king_reach.contains(b) {
+                    for s in king_reach {
+                        king_reach |= MOVEBOARDS.move_board(s, Piece::WhiteKing, Bitboard::new());
+                    }
+                    king_steps += 1;
+                }
+                let mut step_reach = Bitboard::from(a);
+                let mut steps = 0;
+                while !step_reach.contains(b) {
+                    for s in step_reach {
+                        for &(forward, backward) in &DIRECTIONS[..2] {
+                            for &direction in &[forward, backward] {
+                                if let Some(d) = s.shift(direction) {
+                                    step_reach.insert(d);
+                                }
+                            }
+                        }
+                    }
+                    steps += 1;
+                }
+                assert_eq!(MOVEBOARDS.chebyshev_distance(a, b), king_steps);
+                assert_eq!(MOVEBOARDS.manhattan_distance(a, b), steps);
+            }
+        }
+    }
+
+    #[test]
+    fn square_alignment() {
+        for a_idx in 0..64 {
+            for b_idx in 0..64 {
+                let (a, b) = (Square::new(a_idx), Square::new(b_idx));
+                let on_ray = |directions: &[(Direction, Direction)]| {
+                    directions.iter().any(|&(forward, backward)| {
+                        ray(a, forward).contains(&b) || ray(a, backward).contains(&b)
+                    })
+                };
+                assert_eq!(a.same_rank(b), a == b || on_ray(&DIRECTIONS[1..2]));
+                assert_eq!(a.same_file(b), a == b || on_ray(&DIRECTIONS[..1]));
+                assert_eq!(a.same_diagonal(b), a == b || on_ray(&DIRECTIONS[2..]));
+            }
+        }
+    }
+
+    #[test]
+    fn magics_match_reference() {
+        if let Err(mismatch) = verify_sliding_attacks(&MOVEBOARDS.sliding_magics) {
+            panic!("{}", mismatch);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn pext_matches_reference() {
+        let pext = match SlidingPext::new() {
+            Some(pext) => pext,
+            None => {
+                eprintln!("skipping pext_matches_reference, this CPU has no BMI2");
+                return;
+            }
+        };
+        if let Err(mismatch) = verify_sliding_attacks(&pext) {
+            panic!("{}", mismatch);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sliding_backends_agree() {
+        let pext = match SlidingPext::new() {
+            Some(pext) => pext,
+            None => {
+                eprintln!("skipping sliding_backends_agree, this CPU has no BMI2");
+                return;
+            }
+        };
+        let magics = &MOVEBOARDS.sliding_magics;
+        for sqr_idx in 0..64 {
+            let sqr = Square::new(sqr_idx);
+            let bishop_mask = Bitboard::from(sliding_mask(sqr_idx, &BISHOP_DIRECTIONS));
+            for blockers in blocker_subsets(bishop_mask) {
+                assert_eq!(
+                    magics.bishop_moves(sqr, blockers),
+                    pext.bishop_moves(sqr, blockers)
+                );
+            }
+            let rook_mask = Bitboard::from(sliding_mask(sqr_idx, &ROOK_DIRECTIONS));
+            for blockers in blocker_subsets(rook_mask) {
+                assert_eq!(
+                    magics.rook_moves(sqr, blockers),
+                    pext.rook_moves(sqr, blockers)
+                );
+            }
+        }
+    }
+
+    // Checks whichever backend with_sliding_attacks picks on this CPU
+    #[test]
+    fn chosen_backend_matches_reference() {
+        struct Verify;
+
+        impl WithSlidingAttacks for Verify {
+            type Output = Result<(), Mismatch>;
+
+            fn run<S: SlidingAttacks>(self, sliding_attacks: &S) -> Result<(), Mismatch> {
+                verify_sliding_attacks(sliding_attacks)
+            }
+        }
+
+        if let Err(mismatch) = with_sliding_attacks(Verify) {
+            panic!("{}", mismatch);
+        }
+    }
+
+    // Checks whichever backend move_board picks on this CPU
+    #[test]
+    fn move_board_matches_reference() {
+        struct MoveBoardLookups;
+
+        impl SlidingAttacks for MoveBoardLookups {
+            fn bishop_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+                MOVEBOARDS.move_board(sqr, Piece::WhiteBishop, blockers)
+            }
+
+            fn rook_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+                MOVEBOARDS.move_board(sqr, Piece::BlackRook, blockers)
+            }
+        }
+
+        if let Err(mismatch) = verify_sliding_attacks(&MoveBoardLookups) {
+            panic!("{}", mismatch);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::bitboard::Bitboard;
+use crate::boardstructs::Square;
+
+use super::magic::{gen_magics, SquareMagic, BISHOP_DIRECTIONS, ROOK_DIRECTIONS, TABLE_SIZE};
+use super::moveboards::{SlidingAttacks, WithSlidingAttacks};
+
+use std::arch::x86_64::{__cpuid, _pext_u64, CpuidResult};
+use std::convert::TryInto;
+use std::sync::OnceLock;
+
+// PEXT gathers the blocker bits under the mask into a dense index, so unlike
+// magics there is no multiply and no magic number to find. The table has the
+// same layout as the fancy magic one, see
+// https://www.chessprogramming.org/BMI2#PEXTBitboards
+//
+// Only CPUs with BMI2 have PEXT, so the table is built at runtime on those,
+// rather than at compile time into every binary.
+pub struct SlidingPext {
+    bishop_pexts: [SquareMagic; 64],
+    rook_pexts: [SquareMagic; 64],
+    table: Box<[Bitboard; TABLE_SIZE]>,
+}
+
+impl SlidingPext {
+    // None unless the CPU has BMI2, so having a SlidingPext means its
+    // lookups are safe to run
+    pub fn new() -> Option<SlidingPext> {
+        if !is_x86_feature_detected!("bmi2") {
+            return None;
+        }
+        let mut table: Box<[Bitboard; TABLE_SIZE]> = vec![Bitboard::new(); TABLE_SIZE]
+            .into_boxed_slice()
+            .try_into()
+            .unwrap();
+        let (bishop_pexts, offset) = gen_magics(&BISHOP_DIRECTIONS, None, &mut table, 0);
+        let (rook_pexts, _) = gen_magics(&ROOK_DIRECTIONS, None, &mut table, offset);
+        Some(SlidingPext {
+            bishop_pexts,
+            rook_pexts,
+            table,
+        })
+    }
+
+    #[inline]
+    fn lookup(&self, pexts: &[SquareMagic; 64], sqr: Square, blockers: Bitboard) -> Bitboard {
+        let entry = &pexts[u8::from(sqr) as usize];
+        // SAFETY: SlidingPext::new checked that the CPU has BMI2
+        let idx = unsafe { pext(u64::from(blockers), u64::from(entry.mask)) } as usize;
+        self.table[entry.offset + idx]
+    }
+}
+
+impl SlidingAttacks for SlidingPext {
+    #[inline]
+    fn bishop_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+        self.lookup(&self.bishop_pexts, sqr, blockers)
+    }
+
+    #[inline]
+    fn rook_moves(&self, sqr: Square, blockers: Bitboard) -> Bitboard {
+        self.lookup(&self.rook_pexts, sqr, blockers)
+    }
+}
+
+// Built with BMI2 enabled (e.g. -C target-cpu=native) this is an ordinary
+// function that inlines anywhere. Otherwise it only inlines into code that
+// enables BMI2 itself, which is what run_with_pext is for.
+#[cfg(target_feature = "bmi2")]
+#[inline]
+unsafe fn pext(value: u64, mask: u64) -> u64 {
+    _pext_u64(value, mask)
+}
+
+#[cfg(not(target_feature = "bmi2"))]
+#[target_feature(enable = "bmi2")]
+#[inline]
+unsafe fn pext(value: u64, mask: u64) -> u64 {
+    _pext_u64(value, mask)
+}
+
+// Runs work with BMI2 enabled, so that pext can be inlined into its lookups
+// wherever work itself gets inlined here.
+//
+// SAFETY: the CPU must have BMI2, which holding a SlidingPext proves
+#[target_feature(enable = "bmi2")]
+pub(super) unsafe fn run_with_pext<W: WithSlidingAttacks>(
+    work: W,
+    pext: &SlidingPext,
+) -> W::Output {
+    work.run(pext)
+}
+
+// Whether PEXT is worth using over magics. AMD CPUs before Zen 3 (family
+// 19h) implement it in microcode, where it is much slower than magics. So do
+// Hygon's, which are Zen 1 under another vendor string (family 18h).
+fn pext_is_fast() -> bool {
+    let CpuidResult { ebx, ecx, edx, .. } = __cpuid(0);
+    let vendor = (ebx, edx, ecx);
+    let is_amd = vendor == (0x6874_7541, 0x6974_6e65, 0x444d_4163); // "AuthenticAMD"
+    let is_hygon = vendor == (0x6f67_7948, 0x6e65_476e, 0x656e_6975); // "HygonGenuine"
+    if !is_amd && !is_hygon {
+        return true;
+    }
+    let eax = __cpuid(1).eax;
+    let base_family = (eax >> 8) & 0xf;
+    let family = if base_family == 0xf {
+        base_family + ((eax >> 20) & 0xff)
+    } else {
+        base_family
+    };
+    family >= 0x19
+}
+
+// The PEXT backend if this CPU has a fast PEXT, built the first time it's
+// asked for
+pub(super) fn fast_pext() -> Option<&'static SlidingPext> {
+    static FAST_PEXT: OnceLock<Option<SlidingPext>> = OnceLock::new();
+    FAST_PEXT
+        .get_or_init(|| {
+            if pext_is_fast() {
+                SlidingPext::new()
+            } else {
+                None
+            }
+        })
+        .as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::moves::magic::sliding_mask;
+    use crate::moves::verify::blocker_subsets;
+
+    #[test]
+    fn pext_indices_are_dense() {
+        if !is_x86_feature_detected!("bmi2") {
+            eprintln!("skipping pext_indices_are_dense, this CPU has no BMI2");
+            return;
+        }
+        for sqr in 0..64 {
+            let mask = sliding_mask(sqr, &ROOK_DIRECTIONS);
+            for (idx, blockers) in blocker_subsets(Bitboard::from(mask))
+                .into_iter()
+                .enumerate()
+            {
+                // SAFETY: checked for BMI2 above
+                assert_eq!(unsafe { pext(u64::from(blockers), mask) }, idx as u64);
+            }
+        }
+    }
+}
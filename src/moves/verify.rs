@@ -0,0 +1,210 @@
+use crate::bitboard::Bitboard;
+use crate::boardstructs::Square;
+use crate::piece::Piece;
+
+use super::moveboards::{bishop_move_board_slow, rook_move_board_slow, SlidingAttacks};
+
+use std::fmt;
+
+// Exhaustive checking of sliding move lookups against the slow ray walks. For
+// every square, every subset of the squares that can block a piece there is
+// looked up and compared, which covers every distinct position the tables
+// have to handle.
+
+pub struct Mismatch {
+    pub piece: Piece,
+    pub sqr: Square,
+    pub blockers: Bitboard,
+    pub expected: Bitboard,
+    pub found: Bitboard,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} on square {} with blockers {:#018x}: expected {:#018x}, found {:#018x}",
+            self.piece,
+            u8::from(self.sqr),
+            u64::from(self.blockers),
+            u64::from(self.expected),
+            u64::from(self.found)
+        )
+    }
+}
+
+// The squares where a blocker changes the moves from sqr. This is worked out
+// from the reference itself rather than taken from the tables being checked,
+// so that a bad mask can't hide a bad lookup.
+pub fn relevant_blockers<F>(sqr: Square, reference: F) -> Bitboard
+where
+    F: Fn(Square, Bitboard) -> Bitboard,
+{
+    let unblocked = reference(sqr, Bitboard::new());
+    let mut relevant = Bitboard::new();
+    for idx in 0..64 {
+        let blocker = Square::new(idx);
+        if reference(sqr, Bitboard::from(blocker)) != unblocked {
+            relevant.insert(blocker);
+        }
+    }
+    relevant
+}
+
+// Every subset of mask, using the Carry-Rippler trick, see
+// https://www.chessprogramming.org/Traversing_Subsets_of_a_Set
+pub fn blocker_subsets(mask: Bitboard) -> Vec<Bitboard> {
+    let mask = u64::from(mask);
+    let mut subsets = Vec::with_capacity(1 << mask.count_ones());
+    let mut blockers: u64 = 0;
+    loop {
+        subsets.push(Bitboard::from(blockers));
+        blockers = blockers.wrapping_sub(mask) & mask;
+        if blockers == 0 {
+            break;
+        }
+    }
+    subsets
+}
+
+fn verify_piece<F, G>(piece: Piece, lookup: F, reference: G) -> Result<(), Mismatch>
+where
+    F: Fn(Square, Bitboard) -> Bitboard,
+    G: Fn(Square, Bitboard) -> Bitboard,
+{
+    for idx in 0..64 {
+        let sqr = Square::new(idx);
+        for blockers in blocker_subsets(relevant_blockers(sqr, &reference)) {
+            let expected = reference(sqr, blockers);
+            let found = lookup(sqr, blockers);
+            if found != expected {
+                return Err(Mismatch {
+                    piece,
+                    sqr,
+                    blockers,
+                    expected,
+                    found,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Checks every bishop and rook lookup of a backend, returning the first one
+// that disagrees with the reference
+pub fn verify_sliding_attacks<S: SlidingAttacks>(sliding_attacks: &S) -> Result<(), Mismatch> {
+    verify_piece(
+        Piece::WhiteBishop,
+        |sqr, blockers| sliding_attacks.bishop_moves(sqr, blockers),
+        bishop_move_board_slow,
+    )?;
+    verify_piece(
+        Piece::WhiteRook,
+        |sqr, blockers| sliding_attacks.rook_moves(sqr, blockers),
+        rook_move_board_slow,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::moves::moveboards::MOVEBOARDS;
+
+    use proptest::prelude::*;
+
+    // A third, deliberately naive implementation on a 10x12 mailbox, where
+    // the board is surrounded by a border of off-board squares, see
+    // https://www.chessprogramming.org/10x12_Board
+    const BISHOP_OFFSETS: [i8; 4] = [11, -9, -11, 9];
+    const ROOK_OFFSETS: [i8; 4] = [10, 1, -10, -1];
+
+    fn to_mailbox(sqr: u8) -> i8 {
+        21 + (sqr / 8) as i8 * 10 + (sqr % 8) as i8
+    }
+
+    fn from_mailbox(idx: i8) -> Option<u8> {
+        let (rank, file) = (idx / 10 - 2, idx % 10 - 1);
+        if (0..8).contains(&rank) && (0..8).contains(&file) {
+            Some((rank * 8 + file) as u8)
+        } else {
+            None
+        }
+    }
+
+    // Random u64s block almost every ray right next to the piece, so mix in
+    // some sparser boards too
+    fn blockers() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            any::<u64>(),
+            (any::<u64>(), any::<u64>(), any::<u64>()).prop_map(|(a, b, c)| a & b & c),
+        ]
+    }
+
+    fn mailbox_moves(sqr: u8, blockers: u64, offsets: &[i8; 4]) -> u64 {
+        let mut moves = 0;
+        for offset in offsets {
+            let mut idx = to_mailbox(sqr) + offset;
+            while let Some(destination) = from_mailbox(idx) {
+                moves |= 1 << destination;
+                if blockers & (1 << destination) != 0 {
+                    break;
+                }
+                idx += offset;
+            }
+        }
+        moves
+    }
+
+    #[test]
+    fn relevant_blockers_exclude_ray_ends() {
+        let a1 = Square::try_from_str("a1").unwrap();
+        let d4 = Square::try_from_str("d4").unwrap();
+        assert_eq!(relevant_blockers(a1, rook_move_board_slow).len(), 12);
+        assert_eq!(relevant_blockers(a1, bishop_move_board_slow).len(), 6);
+        assert_eq!(relevant_blockers(d4, rook_move_board_slow).len(), 10);
+        assert_eq!(relevant_blockers(d4, bishop_move_board_slow).len(), 9);
+    }
+
+    #[test]
+    fn blocker_subsets_are_complete() {
+        let mask = Bitboard::from(0b1011_0000_0001);
+        let subsets = blocker_subsets(mask);
+        assert_eq!(subsets.len(), 16);
+        for (i, &a) in subsets.iter().enumerate() {
+            assert!(a.is_subset(mask));
+            assert!(subsets[..i].iter().all(|&b| b != a));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn bishop_moves_match_mailbox(sqr in 0u8..64, blockers in blockers()) {
+            prop_assert_eq!(
+                u64::from(MOVEBOARDS.move_board(Square::new(sqr), Piece::WhiteBishop, Bitboard::from(blockers))),
+                mailbox_moves(sqr, blockers, &BISHOP_OFFSETS)
+            );
+        }
+
+        #[test]
+        fn rook_moves_match_mailbox(sqr in 0u8..64, blockers in blockers()) {
+            prop_assert_eq!(
+                u64::from(MOVEBOARDS.move_board(Square::new(sqr), Piece::WhiteRook, Bitboard::from(blockers))),
+                mailbox_moves(sqr, blockers, &ROOK_OFFSETS)
+            );
+        }
+
+        #[test]
+        fn slow_moves_match_mailbox(sqr in 0u8..64, blockers in blockers()) {
+            prop_assert_eq!(
+                u64::from(bishop_move_board_slow(Square::new(sqr), Bitboard::from(blockers))),
+                mailbox_moves(sqr, blockers, &BISHOP_OFFSETS)
+            );
+            prop_assert_eq!(
+                u64::from(rook_move_board_slow(Square::new(sqr), Bitboard::from(blockers))),
+                mailbox_moves(sqr, blockers, &ROOK_OFFSETS)
+            );
+        }
+    }
+}
@@ -1,6 +1,106 @@
+use crate::boardstructs::{Direction, Rank};
 use std::convert::From;
+use std::ops::{Index, IndexMut, Not};
+
+// Color
+#[repr(u8)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    pub const ALL: [Color; 2] = [Color::White, Color::Black];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    // the direction this color's pawns move in
+    pub fn pawn_direction(self) -> Direction {
+        match self {
+            Color::White => Direction::Up,
+            Color::Black => Direction::Down,
+        }
+    }
+
+    // the rank this color's pieces start on
+    pub const fn back_rank(self) -> Rank {
+        match self {
+            Color::White => Rank::new(0),
+            Color::Black => Rank::new(7),
+        }
+    }
+}
+
+impl Not for Color {
+    type Output = Color;
+
+    fn not(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+// PieceType
+#[repr(u8)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+}
+
+impl PieceType {
+    pub const ALL: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    // From the lowercase letter, see the char conversion
+    pub fn try_from_char(c: char) -> Option<PieceType> {
+        PieceType::ALL
+            .iter()
+            .copied()
+            .find(|&piece_type| char::from(piece_type) == c)
+    }
+}
+
+impl From<PieceType> for char {
+    fn from(piece_type: PieceType) -> char {
+        match piece_type {
+            PieceType::Pawn => 'p',
+            PieceType::Knight => 'n',
+            PieceType::Bishop => 'b',
+            PieceType::Rook => 'r',
+            PieceType::Queen => 'q',
+            PieceType::King => 'k',
+        }
+    }
+}
+
+// Piece
+// NOTE: The order of the variants matters, Piece::new and friends rely on
+//       each color's pieces being in PieceType order, white first
 #[repr(u8)]
 #[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Piece {
     WhitePawn,
     WhiteKnight,
@@ -17,22 +117,172 @@ pub enum Piece {
     None,
 }
 
+impl Piece {
+    pub const ALL: [Piece; 12] = [
+        Piece::WhitePawn,
+        Piece::WhiteKnight,
+        Piece::WhiteBishop,
+        Piece::WhiteRook,
+        Piece::WhiteQueen,
+        Piece::WhiteKing,
+        Piece::BlackPawn,
+        Piece::BlackKnight,
+        Piece::BlackBishop,
+        Piece::BlackRook,
+        Piece::BlackQueen,
+        Piece::BlackKing,
+    ];
+
+    pub const fn new(color: Color, piece_type: PieceType) -> Piece {
+        Piece::ALL[color.index() * 6 + piece_type.index()]
+    }
+
+    // None for Piece::None
+    pub fn split(self) -> Option<(Color, PieceType)> {
+        match self {
+            Piece::None => None,
+            _ => Some((
+                Color::ALL[self as usize / 6],
+                PieceType::ALL[self as usize % 6],
+            )),
+        }
+    }
+
+    pub fn color(self) -> Option<Color> {
+        self.split().map(|(color, _)| color)
+    }
+
+    pub fn piece_type(self) -> Option<PieceType> {
+        self.split().map(|(_, piece_type)| piece_type)
+    }
+
+    // The inverse of the char conversion, uppercase for white and lowercase
+    // for black. None for anything else, including '.'.
+    pub fn try_from_char(c: char) -> Option<Piece> {
+        let piece_type = PieceType::try_from_char(c.to_ascii_lowercase())?;
+        let color = if c.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(Piece::new(color, piece_type))
+    }
+}
+
 impl From<Piece> for char {
     fn from(piece: Piece) -> char {
-        match piece {
-            Piece::WhitePawn => 'P',
-            Piece::WhiteKnight => 'N',
-            Piece::WhiteBishop => 'B',
-            Piece::WhiteRook => 'R',
-            Piece::WhiteQueen => 'Q',
-            Piece::WhiteKing => 'K',
-            Piece::BlackPawn => 'p',
-            Piece::BlackKnight => 'n',
-            Piece::BlackBishop => 'b',
-            Piece::BlackRook => 'r',
-            Piece::BlackQueen => 'q',
-            Piece::BlackKing => 'k',
-            Piece::None => '.',
+        match piece.split() {
+            Some((Color::White, piece_type)) => char::from(piece_type).to_ascii_uppercase(),
+            Some((Color::Black, piece_type)) => char::from(piece_type),
+            None => '.',
         }
     }
 }
+
+// Arrays indexed by color and by piece type, for tables that would otherwise
+// need a match on every lookup
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ByColor<T>([T; 2]);
+
+impl<T> ByColor<T> {
+    pub const fn new(white: T, black: T) -> ByColor<T> {
+        ByColor([white, black])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Color, &T)> {
+        Color::ALL.iter().copied().zip(self.0.iter())
+    }
+}
+
+impl<T> Index<Color> for ByColor<T> {
+    type Output = T;
+
+    fn index(&self, color: Color) -> &T {
+        &self.0[color.index()]
+    }
+}
+
+impl<T> IndexMut<Color> for ByColor<T> {
+    fn index_mut(&mut self, color: Color) -> &mut T {
+        &mut self.0[color.index()]
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ByPiece<T>([T; 6]);
+
+impl<T> ByPiece<T> {
+    pub const fn new(pieces: [T; 6]) -> ByPiece<T> {
+        ByPiece(pieces)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (PieceType, &T)> {
+        PieceType::ALL.iter().copied().zip(self.0.iter())
+    }
+}
+
+impl<T> Index<PieceType> for ByPiece<T> {
+    type Output = T;
+
+    fn index(&self, piece_type: PieceType) -> &T {
+        &self.0[piece_type.index()]
+    }
+}
+
+impl<T> IndexMut<PieceType> for ByPiece<T> {
+    fn index_mut(&mut self, piece_type: PieceType) -> &mut T {
+        &mut self.0[piece_type.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn piece_parts() {
+        for &color in &Color::ALL {
+            for &piece_type in &PieceType::ALL {
+                let piece = Piece::new(color, piece_type);
+                assert_eq!(piece.split(), Some((color, piece_type)));
+                assert_eq!(piece.color(), Some(color));
+                assert_eq!(piece.piece_type(), Some(piece_type));
+            }
+        }
+        assert_eq!(
+            Piece::new(Color::Black, PieceType::Queen),
+            Piece::BlackQueen
+        );
+        assert_eq!(Piece::None.split(), None);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(!Color::White, Color::Black);
+        assert_eq!(!Color::Black, Color::White);
+        assert_eq!(Color::Black.back_rank(), Rank::new(7));
+        assert_eq!(Color::White.pawn_direction(), Direction::Up);
+    }
+
+    #[test]
+    fn piece_chars() {
+        assert_eq!(char::from(Piece::WhiteKnight), 'N');
+        assert_eq!(char::from(Piece::BlackKing), 'k');
+        assert_eq!(char::from(Piece::None), '.');
+        for &piece in &Piece::ALL {
+            assert_eq!(Piece::try_from_char(char::from(piece)), Some(piece));
+        }
+        assert_eq!(Piece::try_from_char('.'), None);
+        assert_eq!(Piece::try_from_char('x'), None);
+    }
+
+    #[test]
+    fn typed_arrays() {
+        let mut counts = ByColor::new(ByPiece::new([0; 6]), ByPiece::new([0; 6]));
+        counts[Color::Black][PieceType::Rook] += 2;
+        assert_eq!(counts[Color::Black][PieceType::Rook], 2);
+        assert_eq!(counts[Color::White][PieceType::Rook], 0);
+        assert_eq!(counts[Color::Black].iter().map(|(_, &n)| n).sum::<i32>(), 2);
+    }
+}
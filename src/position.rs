@@ -1,135 +1,80 @@
 use crate::bitboard::{Bitboard};
-use crate::piece::Piece;
-use crate::boardstructs::Square;
-use crate::moves::Move;
+use crate::piece::{ByColor, ByPiece, Color, Piece, PieceType};
+use crate::boardstructs::{BySquare, Direction, File, Shiftable, Square};
+use crate::moves::{Move, MOVEBOARDS};
 
-pub enum Player {
-    White,
-    Black,
-}
+mod castling;
+pub use castling::CastlingRights;
+mod diagram;
+pub use diagram::Diagram;
+mod epd;
+pub use epd::{Epd, EpdError};
+mod fen;
+pub use fen::FenError;
+mod svg;
+pub use svg::Svg;
 
 pub struct Bitboards {
-    white: Bitboard,
-    black: Bitboard,
-    pawns: Bitboard,
-    knights: Bitboard,
-    bishops: Bitboard,
-    rooks: Bitboard,
-    queens: Bitboard,
-    kings: Bitboard,
+    colors: ByColor<Bitboard>,
+    pieces: ByPiece<Bitboard>,
 }
 
 impl Bitboards {
     pub fn piece_bb(&self, piece: Piece) -> Bitboard {
-        match piece {
-            Piece::WhitePawn => self.white & self.pawns,
-            Piece::WhiteKnight => self.white & self.knights,
-            Piece::WhiteBishop => self.white & self.bishops,
-            Piece::WhiteRook => self.white & self.rooks,
-            Piece::WhiteQueen => self.white & self.queens,
-            Piece::WhiteKing => self.white & self.kings,
-            Piece::BlackPawn => self.black & self.pawns,
-            Piece::BlackKnight => self.black & self.knights,
-            Piece::BlackBishop => self.black & self.bishops,
-            Piece::BlackRook => self.black & self.rooks,
-            Piece::BlackQueen => self.black & self.queens,
-            Piece::BlackKing => self.black & self.kings,
-            Piece::None => (self.white | self.black).invert(),
+        match piece.split() {
+            Some((color, piece_type)) => self.colors[color] & self.pieces[piece_type],
+            None => (self.colors[Color::White] | self.colors[Color::Black]).invert(),
+        }
+    }
+
+    // Updates the boards for sqr going from holding old to holding new
+    fn replace(&mut self, sqr: Square, old: Piece, new: Piece) {
+        for piece in &[old, new] {
+            if let Some((color, piece_type)) = piece.split() {
+                self.colors[color] ^= Bitboard::from(sqr);
+                self.pieces[piece_type] ^= Bitboard::from(sqr);
+            }
         }
     }
 }
 
 impl From<&PieceBoard> for Bitboards {
     fn from(pieces: &PieceBoard) -> Bitboards {
-        let mut white = Bitboard::new();
-        let mut black = Bitboard::new();
-        let mut pawns = Bitboard::new();
-        let mut knights = Bitboard::new();
-        let mut bishops = Bitboard::new();
-        let mut rooks = Bitboard::new();
-        let mut queens = Bitboard::new();
-        let mut kings = Bitboard::new();
-
-        for (idx, piece) in pieces.into_iter().enumerate() {
-            let sqr = Square::new(idx as u8);
-            let sqr_bb = Bitboard::from(sqr);
-            match piece {
-                Piece::WhitePawn => {
-                    white |= sqr_bb;
-                    pawns |= sqr_bb;
-                }
-                Piece::WhiteKnight => {
-                    white |= sqr_bb;
-                    knights |= sqr_bb;
-                }
-                Piece::WhiteBishop => {
-                    white |= sqr_bb;
-                    bishops |= sqr_bb;
-                }
-                Piece::WhiteRook => {
-                    white |= sqr_bb;
-                    rooks |= sqr_bb;
-                }
-                Piece::WhiteQueen => {
-                    white |= sqr_bb;
-                    queens |= sqr_bb;
-                }
-                Piece::WhiteKing => {
-                    white |= sqr_bb;
-                    kings |= sqr_bb;
-                }
-                Piece::BlackPawn => {
-                    black |= sqr_bb;
-                    pawns |= sqr_bb;
-                }
-                Piece::BlackKnight => {
-                    black |= sqr_bb;
-                    knights |= sqr_bb;
-                }
-                Piece::BlackBishop => {
-                    black |= sqr_bb;
-                    bishops |= sqr_bb;
-                }
-                Piece::BlackRook => {
-                    black |= sqr_bb;
-                    rooks |= sqr_bb;
-                }
-                Piece::BlackQueen => {
-                    black |= sqr_bb;
-                    queens |= sqr_bb;
-                }
-                Piece::BlackKing => {
-                    black |= sqr_bb;
-                    kings |= sqr_bb;
-                }
-                Piece::None => {}
+        let mut colors = ByColor::new(Bitboard::new(), Bitboard::new());
+        let mut piece_types = ByPiece::new([Bitboard::new(); 6]);
+
+        for (sqr, piece) in pieces.iter() {
+            if let Some((color, piece_type)) = piece.split() {
+                colors[color] |= Bitboard::from(sqr);
+                piece_types[piece_type] |= Bitboard::from(sqr);
             }
         }
         Bitboards {
-            white: white,
-            black: black,
-            pawns: pawns,
-            knights: knights,
-            bishops: bishops,
-            rooks: rooks,
-            queens: queens,
-            kings: kings,
+            colors,
+            pieces: piece_types,
         }
     }
 }
 
 
-pub type PieceBoard = [Piece; 64];
+pub type PieceBoard = BySquare<Piece>;
 
 pub struct Position {
     board: PieceBoard,
     bitboards: Bitboards,
-    current_player: Player,
+    current_player: Color,
+    castling: CastlingRights,
+    // the square a pawn that just moved two squares skipped over
+    en_passant: Option<Square>,
+    // half moves since the last capture or pawn move, for the fifty-move rule
+    halfmove_clock: u16,
+    // starts at 1 and goes up after each black move
+    fullmove_number: u16,
 }
 
 impl Position {
     pub fn starting_position() -> Position {
-        let board = [
+        let board = BySquare::new([
             Piece::WhiteRook,
             Piece::WhiteKnight,
             Piece::WhiteBishop,
@@ -194,32 +139,225 @@ impl Position {
             Piece::BlackBishop,
             Piece::BlackKnight,
             Piece::BlackRook,
-        ];
+        ]);
 
         Position {
-            board: board,
+            board,
             bitboards: Bitboards::from(&board),
-            current_player: Player::White,
+            current_player: Color::White,
+            castling: CastlingRights::standard(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
         }
     }
 
-    // FIXME: check for valid fen / write tests
-    pub fn from_fen(fen: &str) -> Position {
-        unimplemented!()
+    // Builds the Chess960 starting position with the given Scharnagl number,
+    // see https://www.chessprogramming.org/Reinhard_Scharnagl#Chess960Numbering
+    // 518 is the standard starting position, and None for numbers past 959
+    pub fn chess960_starting_position(scharnagl: u16) -> Option<Position> {
+        if scharnagl >= 960 {
+            return None;
+        }
+        // Knight placements among the five squares left after placing the
+        // bishops and the queen, indexed by the remaining quotient
+        const KNIGHTS: [(usize, usize); 10] = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+
+        let mut back_rank: [Option<PieceType>; 8] = [None; 8];
+        let n = scharnagl as usize;
+        // light squared bishop on b, d, f or h
+        back_rank[2 * (n % 4) + 1] = Some(PieceType::Bishop);
+        let n = n / 4;
+        // dark squared bishop on a, c, e or g
+        back_rank[2 * (n % 4)] = Some(PieceType::Bishop);
+        let n = n / 4;
+        let empty = |back_rank: &[Option<PieceType>; 8]| {
+            (0..8)
+                .filter(|&f| back_rank[f].is_none())
+                .collect::<Vec<usize>>()
+        };
+        back_rank[empty(&back_rank)[n % 6]] = Some(PieceType::Queen);
+        let n = n / 6;
+        let (n1, n2) = KNIGHTS[n];
+        let remaining = empty(&back_rank);
+        back_rank[remaining[n1]] = Some(PieceType::Knight);
+        back_rank[remaining[n2]] = Some(PieceType::Knight);
+        // the king always goes between the two rooks
+        let remaining = empty(&back_rank);
+        let (queenside_rook, kingside_rook) = (remaining[0], remaining[2]);
+        back_rank[queenside_rook] = Some(PieceType::Rook);
+        back_rank[remaining[1]] = Some(PieceType::King);
+        back_rank[kingside_rook] = Some(PieceType::Rook);
+
+        let mut board = [Piece::None; 64];
+        for (file, piece_type) in back_rank.iter().enumerate() {
+            let piece_type = piece_type.unwrap();
+            board[file] = Piece::new(Color::White, piece_type);
+            board[8 + file] = Piece::WhitePawn;
+            board[48 + file] = Piece::BlackPawn;
+            board[56 + file] = Piece::new(Color::Black, piece_type);
+        }
+        let board = BySquare::new(board);
+        let kingside_rook = Some(File::new(kingside_rook as u8));
+        let queenside_rook = Some(File::new(queenside_rook as u8));
+
+        Some(Position {
+            board,
+            bitboards: Bitboards::from(&board),
+            current_player: Color::White,
+            castling: CastlingRights {
+                kingside: ByColor::new(kingside_rook, kingside_rook),
+                queenside: ByColor::new(queenside_rook, queenside_rook),
+            },
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        })
+    }
+
+    pub fn current_player(&self) -> Color {
+        self.current_player
+    }
+
+    pub fn castling(&self) -> CastlingRights {
+        self.castling
+    }
+
+    pub fn en_passant(&self) -> Option<Square> {
+        self.en_passant
     }
 
     pub fn piece_on(&self, sqr: Square) -> Piece {
-        self.board[u8::from(sqr) as usize]
+        self.board[sqr]
     }
 
     pub fn set_piece_on(&mut self, piece: Piece, sqr: Square) {
-        self.board[u8::from(sqr) as usize] = piece
+        self.bitboards.replace(sqr, self.board[sqr], piece);
+        self.board[sqr] = piece
     }
 
+    // Plays mv, which is assumed to be legal, and updates the rest of the
+    // position to match: the side to move, castling rights, the en passant
+    // square and both clocks. Castling can be given either as the king moving
+    // two squares or as the king taking its own rook, as in Chess960.
     pub fn make_move(&mut self, mv: Move) {
-        let piece = self.piece_on(mv.from());
-        self.set_piece_on(Piece::None, mv.from());
-        self.set_piece_on(piece, mv.to());
+        let (from, to) = (mv.from(), mv.to());
+        let piece = self.piece_on(from);
+        let (us, piece_type) = piece.split().expect("make_move needs a piece to move");
+        let captured = self.piece_on(to);
+        let rook = Piece::new(us, PieceType::Rook);
+        let file_distance = i16::from(u8::from(to.file())) - i16::from(u8::from(from.file()));
+
+        let mut is_capture = captured != Piece::None;
+        if piece_type == PieceType::King && (captured == rook || file_distance.abs() == 2) {
+            is_capture = false;
+            let kingside = file_distance > 0;
+            let rook_file = if captured == rook {
+                Some(to.file())
+            } else if kingside {
+                self.castling.kingside[us]
+            } else {
+                self.castling.queenside[us]
+            };
+            let rook_file = rook_file.expect("make_move needs castling rights to castle");
+            // the king and rook end up on the same files as in standard chess
+            let (king_file, rook_to) = if kingside { (6, 5) } else { (2, 3) };
+            let back_rank = us.back_rank();
+            self.set_piece_on(Piece::None, from);
+            self.set_piece_on(Piece::None, Square::from_coords(back_rank, rook_file));
+            self.set_piece_on(piece, Square::from_coords(back_rank, File::new(king_file)));
+            self.set_piece_on(rook, Square::from_coords(back_rank, File::new(rook_to)));
+        } else {
+            if piece_type == PieceType::Pawn && Some(to) == self.en_passant {
+                // the captured pawn is just past the en passant square
+                let pushed_pawn = to.shift((!us).pawn_direction()).unwrap();
+                self.set_piece_on(Piece::None, pushed_pawn);
+                is_capture = true;
+            }
+            let piece = match mv.promotion() {
+                Some(promotion) => Piece::new(us, promotion),
+                None => piece,
+            };
+            self.set_piece_on(Piece::None, from);
+            self.set_piece_on(piece, to);
+        }
+
+        // moving the king loses both rights, moving or capturing a rook loses
+        // the right that goes with it
+        if piece_type == PieceType::King {
+            self.castling.kingside[us] = None;
+            self.castling.queenside[us] = None;
+        }
+        for &color in &Color::ALL {
+            let touched = |rook_file: Option<File>| {
+                rook_file.is_some_and(|file| {
+                    let sqr = Square::from_coords(color.back_rank(), file);
+                    sqr == from || sqr == to
+                })
+            };
+            if touched(self.castling.kingside[color]) {
+                self.castling.kingside[color] = None;
+            }
+            if touched(self.castling.queenside[color]) {
+                self.castling.queenside[color] = None;
+            }
+        }
+
+        let rank_distance = i16::from(u8::from(to.rank())) - i16::from(u8::from(from.rank()));
+        self.en_passant = if piece_type == PieceType::Pawn && rank_distance.abs() == 2 {
+            from.shift(us.pawn_direction())
+        } else {
+            None
+        };
+
+        if piece_type == PieceType::Pawn || is_capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if us == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.current_player = !us;
+    }
+
+    // The side to move's king if it is in check
+    fn checked_king(&self) -> Option<Square> {
+        let bitboards = &self.bitboards;
+        let us = self.current_player;
+        let king = bitboards.piece_bb(Piece::new(us, PieceType::King)).lsb()?;
+        let theirs = |piece_type| bitboards.piece_bb(Piece::new(!us, piece_type));
+        let occupied = bitboards.piece_bb(Piece::None).invert();
+        let moves = |piece_type| MOVEBOARDS.move_board(king, Piece::new(us, piece_type), occupied);
+
+        // enemy pawns attacking the king sit diagonally in front of it
+        let king_bb = Bitboard::from(king);
+        let pawn_squares = match us {
+            Color::White => king_bb.shift(Direction::UpLeft) | king_bb.shift(Direction::UpRight),
+            Color::Black => king_bb.shift(Direction::DownLeft) | king_bb.shift(Direction::DownRight),
+        };
+
+        let checkers = (pawn_squares & theirs(PieceType::Pawn))
+            | (moves(PieceType::Knight) & theirs(PieceType::Knight))
+            | (moves(PieceType::Bishop) & (theirs(PieceType::Bishop) | theirs(PieceType::Queen)))
+            | (moves(PieceType::Rook) & (theirs(PieceType::Rook) | theirs(PieceType::Queen)))
+            | (moves(PieceType::King) & theirs(PieceType::King));
+        if checkers.is_empty() {
+            None
+        } else {
+            Some(king)
+        }
     }
 }
 
@@ -231,6 +369,8 @@ mod tests {
     fn size_of_types() {
         assert_eq!(std::mem::size_of::<Square>(), 1);
         assert_eq!(std::mem::size_of::<Piece>(), 1);
+        assert_eq!(std::mem::size_of::<Color>(), 1);
+        assert_eq!(std::mem::size_of::<PieceBoard>(), 64);
     }
 
     #[test]
@@ -308,6 +448,37 @@ mod tests {
         // assert_eq!(board.piece_on(Square::try_from(33)), Piece::None);
     }
 
+    #[test]
+    fn chess960_starting_position() {
+        assert_eq!(
+            Position::chess960_starting_position(518).unwrap().board,
+            Position::starting_position().board
+        );
+
+        let board = Position::chess960_starting_position(0).unwrap();
+        let back_rank = [
+            Piece::WhiteBishop,
+            Piece::WhiteBishop,
+            Piece::WhiteQueen,
+            Piece::WhiteKnight,
+            Piece::WhiteKnight,
+            Piece::WhiteRook,
+            Piece::WhiteKing,
+            Piece::WhiteRook,
+        ];
+        for (file, &piece) in back_rank.iter().enumerate() {
+            assert_eq!(board.piece_on(Square::new(file as u8)), piece);
+        }
+        assert_eq!(
+            board.piece_on(Square::try_from_str("g8").unwrap()),
+            Piece::BlackKing
+        );
+        assert_eq!(board.castling().kingside[Color::Black], Some(File::new(7)));
+        assert_eq!(board.castling().queenside[Color::Black], Some(File::new(5)));
+        assert!(Position::chess960_starting_position(959).is_some());
+        assert!(Position::chess960_starting_position(960).is_none());
+    }
+
     #[test]
     fn make_move() {
         let mut board = Position::starting_position();
@@ -315,24 +486,84 @@ mod tests {
         let sqr_e4 = Square::try_from_str("e4").unwrap();
         let sqr_d7 = Square::try_from_str("d7").unwrap();
         let sqr_d5 = Square::try_from_str("d5").unwrap();
-        board.make_move(Move {
-            from: sqr_e2,
-            to: sqr_e4,
-            promotion: false,
-            capture: false,
-        });
+        board.make_move(Move::new(sqr_e2, sqr_e4, None));
         assert_eq!(board.piece_on(sqr_e2), Piece::None);
         assert_eq!(board.piece_on(sqr_e4), Piece::WhitePawn);
 
-        board.make_move(Move {
-            from: sqr_d7,
-            to: sqr_d5,
-            promotion: false,
-            capture: false,
-        });
+        board.make_move(Move::new(sqr_d7, sqr_d5, None));
         assert_eq!(board.piece_on(sqr_e2), Piece::None);
         assert_eq!(board.piece_on(sqr_e4), Piece::WhitePawn);
         assert_eq!(board.piece_on(sqr_d7), Piece::None);
         assert_eq!(board.piece_on(sqr_d5), Piece::BlackPawn);
     }
+
+    // Plays each move from fen and checks the FEN after it, that the FEN
+    // parses back to the same position, and that the bitboards kept up
+    fn check_moves(fen: &str, moves: &[(&str, &str)]) {
+        let mut position = Position::from_fen(fen).unwrap();
+        for &(mv, expected) in moves {
+            position.make_move(mv.parse().unwrap());
+            assert_eq!(position.to_fen(), expected, "after {}", mv);
+            assert_eq!(Position::from_fen(expected).unwrap().to_fen(), expected);
+            let bitboards = Bitboards::from(&position.board);
+            for &piece in Piece::ALL.iter().chain(&[Piece::None]) {
+                assert_eq!(
+                    position.bitboards.piece_bb(piece),
+                    bitboards.piece_bb(piece)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn make_move_state() {
+        check_moves(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[
+                (
+                    "e2e4",
+                    "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                ),
+                (
+                    "e7e5",
+                    "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+                ),
+                (
+                    "e1e2",
+                    "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPPKPPP/RNBQ1BNR b kq - 1 2",
+                ),
+                (
+                    "g8f6",
+                    "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/8/PPPPKPPP/RNBQ1BNR w kq - 2 3",
+                ),
+            ],
+        );
+        // castling, then rook moves and captures using up the other rights
+        check_moves(
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            &[
+                ("e1g1", "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1"),
+                ("a8a1", "4k2r/8/8/8/8/8/8/r4RK1 w k - 0 2"),
+                ("f1f8", "4kR1r/8/8/8/8/8/8/r5K1 b k - 1 2"),
+                ("h8f8", "4kr2/8/8/8/8/8/8/r5K1 w - - 0 3"),
+            ],
+        );
+        // en passant and promotion
+        check_moves(
+            "4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            &[
+                ("e5d6", "4k3/1P6/3P4/8/8/8/8/4K3 b - - 0 1"),
+                ("e8f7", "8/1P3k2/3P4/8/8/8/8/4K3 w - - 1 2"),
+                ("b7b8q", "1Q6/5k2/3P4/8/8/8/8/4K3 b - - 0 2"),
+            ],
+        );
+        // Chess960 castling, with the king taking its own rook
+        check_moves(
+            "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1",
+            &[
+                ("e1g1", "1r2k1r1/8/8/8/8/8/8/1R3RK1 b kq - 1 1"),
+                ("e8b8", "2kr2r1/8/8/8/8/8/8/1R3RK1 w - - 2 2"),
+            ],
+        );
+    }
 }
@@ -0,0 +1,185 @@
+use crate::boardstructs::{File, Square};
+use crate::piece::{ByColor, Color, Piece, PieceType};
+
+use super::PieceBoard;
+
+// The files of the rooks each color may still castle with, on either side of
+// its king. Files rather than flags, since in Chess960 the rooks can start on
+// any file.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct CastlingRights {
+    pub kingside: ByColor<Option<File>>,
+    pub queenside: ByColor<Option<File>>,
+}
+
+impl CastlingRights {
+    pub const NONE: CastlingRights = CastlingRights {
+        kingside: ByColor::new(None, None),
+        queenside: ByColor::new(None, None),
+    };
+
+    // both sides for both colors, with the rooks in the corners
+    pub fn standard() -> CastlingRights {
+        CastlingRights {
+            kingside: ByColor::new(Some(File::new(7)), Some(File::new(7))),
+            queenside: ByColor::new(Some(File::new(0)), Some(File::new(0))),
+        }
+    }
+
+    // The castling field of a FEN string: "-", or at most one right per color
+    // and side, each either K or Q for the outermost rook on that side of the
+    // king (X-FEN) or the rook's file (Shredder-FEN), upper case for white.
+    // Either way the king and the rook have to be on their back rank.
+    pub fn from_fen(s: &str, board: &PieceBoard) -> Option<CastlingRights> {
+        let mut castling = CastlingRights::NONE;
+        if s == "-" {
+            return Some(castling);
+        }
+        if s.is_empty() {
+            return None;
+        }
+        for c in s.chars() {
+            let color = if c.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            let king = back_rank_king(board, color)?;
+            let (kingside, file) = match c.to_ascii_lowercase() {
+                'k' => (true, outermost_rook(board, color, true)?),
+                'q' => (false, outermost_rook(board, color, false)?),
+                c @ 'a'..='h' => {
+                    let file = File::new(c as u8 - b'a');
+                    let rook = Piece::new(color, PieceType::Rook);
+                    if file == king || board[Square::from_coords(color.back_rank(), file)] != rook {
+                        return None;
+                    }
+                    (u8::from(file) > u8::from(king), file)
+                }
+                _ => return None,
+            };
+            let rook = if kingside {
+                &mut castling.kingside[color]
+            } else {
+                &mut castling.queenside[color]
+            };
+            if rook.is_some() {
+                return None;
+            }
+            *rook = Some(file);
+        }
+        Some(castling)
+    }
+
+    // The castling field of a FEN string, as X-FEN writes it: K or Q when the
+    // rook is the outermost one on its side of the king, otherwise its file
+    pub fn to_fen(&self, board: &PieceBoard) -> String {
+        let mut fen = String::new();
+        for &color in &[Color::White, Color::Black] {
+            for &(rook, kingside) in &[(self.kingside[color], true), (self.queenside[color], false)]
+            {
+                if let Some(file) = rook {
+                    let c = if outermost_rook(board, color, kingside) != Some(file) {
+                        (b'a' + u8::from(file)) as char
+                    } else if kingside {
+                        'k'
+                    } else {
+                        'q'
+                    };
+                    fen.push(match color {
+                        Color::White => c.to_ascii_uppercase(),
+                        Color::Black => c,
+                    });
+                }
+            }
+        }
+        if fen.is_empty() {
+            fen.push('-');
+        }
+        fen
+    }
+}
+
+// The file of color's king, if it's on color's back rank
+fn back_rank_king(board: &PieceBoard, color: Color) -> Option<File> {
+    let king = Piece::new(color, PieceType::King);
+    (0..8)
+        .map(File::new)
+        .find(|&file| board[Square::from_coords(color.back_rank(), file)] == king)
+}
+
+// The file of color's rook furthest from its king on one side, on color's
+// back rank
+fn outermost_rook(board: &PieceBoard, color: Color, kingside: bool) -> Option<File> {
+    let king = u8::from(back_rank_king(board, color)?);
+    let rook = Piece::new(color, PieceType::Rook);
+    let mut rooks = (0..8).map(File::new).filter(|&file| {
+        let on_side = if kingside {
+            u8::from(file) > king
+        } else {
+            u8::from(file) < king
+        };
+        on_side && board[Square::from_coords(color.back_rank(), file)] == rook
+    });
+    if kingside {
+        rooks.next_back()
+    } else {
+        rooks.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::position::Position;
+
+    #[test]
+    fn standard_castling() {
+        let board = Position::starting_position().board;
+        for s in &["KQkq", "HAha", "KAhq"] {
+            assert_eq!(
+                CastlingRights::from_fen(s, &board),
+                Some(CastlingRights::standard())
+            );
+        }
+        assert_eq!(CastlingRights::standard().to_fen(&board), "KQkq");
+        assert_eq!(
+            CastlingRights::from_fen("-", &board),
+            Some(CastlingRights::NONE)
+        );
+        assert_eq!(CastlingRights::NONE.to_fen(&board), "-");
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // bbqnnrkr, so the rooks are on f and h either side of the king on g
+        let board = Position::chess960_starting_position(0).unwrap().board;
+        let castling = CastlingRights::from_fen("KQkq", &board).unwrap();
+        assert_eq!(castling.kingside[Color::White], Some(File::new(7)));
+        assert_eq!(castling.queenside[Color::White], Some(File::new(5)));
+        assert_eq!(CastlingRights::from_fen("HFhf", &board), Some(castling));
+        assert_eq!(castling.to_fen(&board), "KQkq");
+
+        // With two rooks on one side K means the outer one, and the inner one
+        // needs its file
+        let mut board = Position::starting_position().board;
+        board[Square::new(6)] = Piece::WhiteRook;
+        let castling = CastlingRights::from_fen("Gk", &board).unwrap();
+        assert_eq!(castling.kingside[Color::White], Some(File::new(6)));
+        assert_eq!(castling.kingside[Color::Black], Some(File::new(7)));
+        assert_eq!(castling.to_fen(&board), "Gk");
+        let castling = CastlingRights::from_fen("KQ", &board).unwrap();
+        assert_eq!(castling.kingside[Color::White], Some(File::new(7)));
+        assert_eq!(CastlingRights::from_fen("HQ", &board), Some(castling));
+        assert_eq!(castling.to_fen(&board), "KQ");
+    }
+
+    #[test]
+    fn invalid_castling() {
+        let board = Position::starting_position().board;
+        for s in &["", "KK", "KQkqX", "-K", "KH", "E", "B", "Kb"] {
+            assert_eq!(CastlingRights::from_fen(s, &board), None, "{}", s);
+        }
+    }
+}
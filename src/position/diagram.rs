@@ -0,0 +1,275 @@
+use crate::bitboard::Bitboard;
+use crate::boardstructs::{File, Rank, Square};
+use crate::moves::Move;
+use crate::piece::{Color, Piece, PieceType};
+
+use super::Position;
+
+use std::fmt;
+
+// ANSI background colors (256 color palette) for the different kinds of
+// square, see https://en.wikipedia.org/wiki/ANSI_escape_code#8-bit
+const LIGHT_SQUARE: u8 = 180;
+const DARK_SQUARE: u8 = 137;
+const LAST_MOVE: u8 = 143;
+const HIGHLIGHT: u8 = 74;
+const CHECK: u8 = 160;
+
+// A printable board diagram with rank and file labels. Plain diagrams are
+// ASCII, using the usual piece letters, and are what Position's Display
+// prints. Highlights only show up in ANSI mode.
+pub struct Diagram<'a> {
+    position: &'a Position,
+    unicode: bool,
+    ansi: bool,
+    flipped: bool,
+    last_move: Option<Move>,
+    highlights: Bitboard,
+}
+
+impl Position {
+    pub fn diagram(&self) -> Diagram<'_> {
+        Diagram {
+            position: self,
+            unicode: false,
+            ansi: false,
+            flipped: false,
+            last_move: None,
+            highlights: Bitboard::new(),
+        }
+    }
+}
+
+impl<'a> Diagram<'a> {
+    // Unicode chess glyphs instead of letters
+    pub fn unicode(mut self) -> Self {
+        self.unicode = true;
+        self
+    }
+
+    // Colored squares, with the last move, a king in check and the
+    // highlighted squares picked out
+    pub fn ansi(mut self) -> Self {
+        self.ansi = true;
+        self
+    }
+
+    // From black's side, with rank 1 at the top and the h-file on the left
+    pub fn flipped(mut self) -> Self {
+        self.flipped = true;
+        self
+    }
+
+    pub fn last_move(mut self, mv: Move) -> Self {
+        self.last_move = Some(mv);
+        self
+    }
+
+    pub fn highlight(mut self, squares: Bitboard) -> Self {
+        self.highlights |= squares;
+        self
+    }
+
+    fn piece_char(&self, piece: Piece) -> char {
+        if !self.unicode {
+            return char::from(piece);
+        }
+        match piece.split() {
+            Some((Color::White, PieceType::Pawn)) => '♙',
+            Some((Color::White, PieceType::Knight)) => '♘',
+            Some((Color::White, PieceType::Bishop)) => '♗',
+            Some((Color::White, PieceType::Rook)) => '♖',
+            Some((Color::White, PieceType::Queen)) => '♕',
+            Some((Color::White, PieceType::King)) => '♔',
+            Some((Color::Black, PieceType::Pawn)) => '♟',
+            Some((Color::Black, PieceType::Knight)) => '♞',
+            Some((Color::Black, PieceType::Bishop)) => '♝',
+            Some((Color::Black, PieceType::Rook)) => '♜',
+            Some((Color::Black, PieceType::Queen)) => '♛',
+            Some((Color::Black, PieceType::King)) => '♚',
+            None => '.',
+        }
+    }
+
+    fn background(&self, sqr: Square, checked_king: Option<Square>) -> u8 {
+        if checked_king == Some(sqr) {
+            CHECK
+        } else if self.highlights.contains(sqr) {
+            HIGHLIGHT
+        } else if self
+            .last_move
+            .is_some_and(|mv| mv.from() == sqr || mv.to() == sqr)
+        {
+            LAST_MOVE
+        } else if Bitboard::LIGHT_SQUARES.contains(sqr) {
+            LIGHT_SQUARE
+        } else {
+            DARK_SQUARE
+        }
+    }
+}
+
+impl<'a> fmt::Display for Diagram<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ranks = Rank::ALL;
+        let mut files = File::ALL;
+        if self.flipped {
+            files.reverse();
+        } else {
+            ranks.reverse();
+        }
+        let checked_king = if self.ansi {
+            self.position.checked_king()
+        } else {
+            None
+        };
+
+        for &rank in &ranks {
+            write!(f, "{}", rank)?;
+            for &file in &files {
+                let sqr = Square::from_coords(rank, file);
+                let piece = self.position.piece_on(sqr);
+                let c = self.piece_char(piece);
+                if self.ansi {
+                    let c = if piece == Piece::None { ' ' } else { c };
+                    // bright white pieces for white, black for black
+                    let foreground = if piece.color() == Some(Color::White) {
+                        97
+                    } else {
+                        30
+                    };
+                    write!(
+                        f,
+                        "\x1b[48;5;{};{}m {} ",
+                        self.background(sqr, checked_king),
+                        foreground,
+                        c
+                    )?;
+                } else {
+                    write!(f, " {}", c)?;
+                }
+            }
+            if self.ansi {
+                write!(f, "\x1b[0m")?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, " ")?;
+        for &file in &files {
+            if self.ansi {
+                write!(f, " {} ", file)?;
+            } else {
+                write!(f, " {}", file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// An 8x8 diagram from white's side, see Position::diagram for other views
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.diagram().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STARTING_DIAGRAM: &str = "\
+8 r n b q k b n r
+7 p p p p p p p p
+6 . . . . . . . .
+5 . . . . . . . .
+4 . . . . . . . .
+3 . . . . . . . .
+2 P P P P P P P P
+1 R N B Q K B N R
+  a b c d e f g h";
+
+    #[test]
+    fn ascii_diagram() {
+        let position = Position::starting_position();
+        assert_eq!(position.to_string(), STARTING_DIAGRAM);
+        assert_eq!(position.diagram().to_string(), STARTING_DIAGRAM);
+    }
+
+    #[test]
+    fn flipped_unicode_diagram() {
+        let diagram = Position::starting_position()
+            .diagram()
+            .unicode()
+            .flipped()
+            .to_string();
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[0], "1 ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖");
+        assert_eq!(lines[7], "8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜");
+        assert_eq!(lines[8], "  h g f e d c b a");
+    }
+
+    #[test]
+    fn ansi_highlights() {
+        let mut position = Position::starting_position();
+        // a black bishop on b4 checking the white king through d2
+        position.set_piece_on(Piece::None, Square::D2);
+        position.set_piece_on(Piece::BlackBishop, Square::B4);
+        let diagram = position
+            .diagram()
+            .ansi()
+            .highlight(Bitboard::from(Square::H3))
+            .to_string();
+        let lines: Vec<&str> = diagram.lines().collect();
+
+        let square = |color: u8, c: char, foreground: u8| {
+            format!("\x1b[48;5;{};{}m {} ", color, foreground, c)
+        };
+        assert!(lines[7].contains(&square(CHECK, 'K', 97)));
+        assert!(lines[4].contains(&square(DARK_SQUARE, 'b', 30)));
+        assert!(lines[5].ends_with(&format!("{}\x1b[0m", square(HIGHLIGHT, ' ', 30))));
+        assert!(lines[0].starts_with(&format!("8{}", square(LIGHT_SQUARE, 'r', 30))));
+        assert_eq!(lines[8], "  a  b  c  d  e  f  g  h ");
+
+        // nothing is in check at the start
+        assert_eq!(
+            Position::starting_position()
+                .diagram()
+                .ansi()
+                .to_string()
+                .matches(&format!("48;5;{}", CHECK))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn ansi_after_moves() {
+        // fool's mate, so make_move has to hand the move to white for the
+        // check to show up
+        let mut position = Position::starting_position();
+        let mut last_move = None;
+        for mv in &["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let mv: Move = mv.parse().unwrap();
+            position.make_move(mv);
+            last_move = Some(mv);
+        }
+        let diagram = position
+            .diagram()
+            .ansi()
+            .last_move(last_move.unwrap())
+            .to_string();
+        let lines: Vec<&str> = diagram.lines().collect();
+
+        let square = |color: u8, c: char, foreground: u8| {
+            format!("\x1b[48;5;{};{}m {} ", color, foreground, c)
+        };
+        assert!(lines[0].contains(&format!(
+            "{}{}",
+            square(LIGHT_SQUARE, 'b', 30),
+            square(LAST_MOVE, ' ', 30)
+        )));
+        assert!(lines[4].ends_with(&format!("{}\x1b[0m", square(LAST_MOVE, 'q', 30))));
+        assert!(lines[7].contains(&square(CHECK, 'K', 97)));
+        assert_eq!(diagram.matches(&format!("48;5;{};", LAST_MOVE)).count(), 2);
+    }
+}
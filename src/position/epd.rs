@@ -0,0 +1,336 @@
+use super::{FenError, Position};
+
+use std::fmt;
+use std::str::FromStr;
+
+// Extended Position Description, the format test suites like WAC and STS come
+// in: the first four FEN fields followed by operations, each an opcode and its
+// operands ending with ';'. See
+// https://www.chessprogramming.org/Extended_Position_Description
+
+#[derive(PartialEq, Debug)]
+pub enum EpdError {
+    InvalidFen(FenError),
+    // a string operand with no closing quote, from its opening quote on
+    UnterminatedString(String),
+    // an operation with a bad opcode, no ';' after it, or operands that don't
+    // fit its opcode
+    InvalidOperation(String),
+    DuplicateOpcode(String),
+}
+
+impl fmt::Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpdError::InvalidFen(err) => write!(f, "{}", err),
+            EpdError::UnterminatedString(s) => write!(f, "unterminated EPD string {}", s),
+            EpdError::InvalidOperation(s) => write!(f, "invalid EPD operation \"{}\"", s),
+            EpdError::DuplicateOpcode(s) => write!(f, "duplicate EPD opcode \"{}\"", s),
+        }
+    }
+}
+
+// One EPD record. Moves are kept in SAN as written, since resolving them
+// against the position needs legal move generation.
+pub struct Epd {
+    pub position: Position,
+    // bm, the moves a solver should find
+    pub best_moves: Vec<String>,
+    // am, the moves a solver should avoid
+    pub avoid_moves: Vec<String>,
+    pub id: Option<String>,
+    // c0 to c9
+    pub comments: [Option<String>; 10],
+    // acd, the depth of the analysis that produced ce and pv
+    pub acd: Option<u32>,
+    // ce, the evaluation in centipawns from the side to move's point of view
+    pub ce: Option<i32>,
+    pub pv: Vec<String>,
+    // every other operation, in the order they were read
+    pub other: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn new(position: Position) -> Epd {
+        Epd {
+            position,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            id: None,
+            comments: Default::default(),
+            acd: None,
+            ce: None,
+            pv: Vec::new(),
+            other: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for Epd {
+    type Err = EpdError;
+
+    fn from_str(s: &str) -> Result<Epd, EpdError> {
+        let mut rest = s.trim();
+        let mut fields = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            fields.push(&rest[..end]);
+            rest = rest[end..].trim_start();
+        }
+        let position = Position::from_fen(&fields.join(" ")).map_err(EpdError::InvalidFen)?;
+
+        let mut epd = Epd::new(position);
+        let mut seen = Vec::new();
+        for (opcode, operands) in parse_operations(rest)? {
+            if seen.contains(&opcode) {
+                return Err(EpdError::DuplicateOpcode(opcode));
+            }
+            let invalid = || {
+                let mut operation = opcode.clone();
+                for operand in &operands {
+                    operation.push(' ');
+                    operation.push_str(operand);
+                }
+                EpdError::InvalidOperation(operation)
+            };
+            let single = || match operands.as_slice() {
+                [operand] => Ok(operand.clone()),
+                _ => Err(invalid()),
+            };
+            let moves = || {
+                if operands.is_empty() {
+                    Err(invalid())
+                } else {
+                    Ok(operands.clone())
+                }
+            };
+            match opcode.as_str() {
+                "bm" => epd.best_moves = moves()?,
+                "am" => epd.avoid_moves = moves()?,
+                "pv" => epd.pv = moves()?,
+                "id" => epd.id = Some(single()?),
+                "acd" => epd.acd = Some(single()?.parse().map_err(|_| invalid())?),
+                "ce" => epd.ce = Some(single()?.parse().map_err(|_| invalid())?),
+                _ => match comment_index(&opcode) {
+                    Some(idx) => epd.comments[idx] = Some(single()?),
+                    None => epd.other.push((opcode.clone(), operands.clone())),
+                },
+            }
+            seen.push(opcode);
+        }
+        Ok(epd)
+    }
+}
+
+// The FEN fields, then the operations in the order they're listed on Epd
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fen = self.position.to_fen();
+        let fields: Vec<&str> = fen.split(' ').take(4).collect();
+        write!(f, "{}", fields.join(" "))?;
+
+        write_operation(f, "bm", &self.best_moves, false)?;
+        write_operation(f, "am", &self.avoid_moves, false)?;
+        if let Some(id) = &self.id {
+            write_operation(f, "id", std::slice::from_ref(id), true)?;
+        }
+        for (idx, comment) in self.comments.iter().enumerate() {
+            if let Some(comment) = comment {
+                write_operation(f, &format!("c{}", idx), std::slice::from_ref(comment), true)?;
+            }
+        }
+        if let Some(acd) = self.acd {
+            write_operation(f, "acd", &[acd.to_string()], false)?;
+        }
+        if let Some(ce) = self.ce {
+            write_operation(f, "ce", &[ce.to_string()], false)?;
+        }
+        write_operation(f, "pv", &self.pv, false)?;
+        for (opcode, operands) in &self.other {
+            write!(f, " {}", opcode)?;
+            for operand in operands {
+                write_operand(f, operand, false)?;
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+// Splits everything after the FEN fields into opcodes and their operands.
+// Operands are separated by whitespace, unless they are in double quotes,
+// which can hold spaces and semicolons.
+fn parse_operations(s: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut rest = s.trim_start();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix(';') {
+            if tokens.is_empty() {
+                return Err(EpdError::InvalidOperation(String::new()));
+            }
+            let opcode = tokens.remove(0);
+            let mut chars = opcode.chars();
+            let is_opcode = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_opcode {
+                return Err(EpdError::InvalidOperation(opcode));
+            }
+            operations.push((opcode, std::mem::take(&mut tokens)));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('"') {
+            let end = after
+                .find('"')
+                .ok_or_else(|| EpdError::UnterminatedString(String::from(rest)))?;
+            tokens.push(String::from(&after[..end]));
+            rest = &after[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || c == ';' || c == '"')
+                .unwrap_or(rest.len());
+            tokens.push(String::from(&rest[..end]));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    if !tokens.is_empty() {
+        return Err(EpdError::InvalidOperation(tokens.join(" ")));
+    }
+    Ok(operations)
+}
+
+// 0 to 9 for the comment opcodes c0 to c9
+fn comment_index(opcode: &str) -> Option<usize> {
+    match opcode.as_bytes() {
+        [b'c', digit @ b'0'..=b'9'] => Some((digit - b'0') as usize),
+        _ => None,
+    }
+}
+
+fn write_operation(
+    f: &mut fmt::Formatter,
+    opcode: &str,
+    operands: &[String],
+    quoted: bool,
+) -> fmt::Result {
+    if operands.is_empty() {
+        return Ok(());
+    }
+    write!(f, " {}", opcode)?;
+    for operand in operands {
+        write_operand(f, operand, quoted)?;
+    }
+    write!(f, ";")
+}
+
+// Operands that wouldn't read back as a single token are quoted as well
+fn write_operand(f: &mut fmt::Formatter, operand: &str, quoted: bool) -> fmt::Result {
+    let needs_quotes =
+        operand.is_empty() || operand.contains(|c: char| c.is_whitespace() || c == ';');
+    if quoted || needs_quotes {
+        write!(f, " \"{}\"", operand)
+    } else {
+        write!(f, " {}", operand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str =
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";";
+
+    #[test]
+    fn wac_line() {
+        let epd: Epd = WAC_001.parse().unwrap();
+        assert_eq!(
+            epd.position.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(epd.best_moves, vec![String::from("Qg6")]);
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        assert!(epd.avoid_moves.is_empty());
+        assert_eq!(epd.to_string(), WAC_001);
+    }
+
+    #[test]
+    fn all_opcodes() {
+        let line = concat!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ",
+            "c9 \"last\";  am  a4 h4 ;c0 \"first; with a semicolon\"; ",
+            "hmvc 0; acd 12; ce -35; id \"start\"; pv e4 e5 Nf3; bm e4 d4; noop;"
+        );
+        let epd: Epd = line.parse().unwrap();
+        assert_eq!(epd.best_moves, vec!["e4", "d4"]);
+        assert_eq!(epd.avoid_moves, vec!["a4", "h4"]);
+        assert_eq!(epd.pv, vec!["e4", "e5", "Nf3"]);
+        assert_eq!(epd.id.as_deref(), Some("start"));
+        assert_eq!(epd.comments[0].as_deref(), Some("first; with a semicolon"));
+        assert_eq!(epd.comments[9].as_deref(), Some("last"));
+        assert_eq!(epd.comments[1], None);
+        assert_eq!(epd.acd, Some(12));
+        assert_eq!(epd.ce, Some(-35));
+        assert_eq!(
+            epd.other,
+            vec![
+                (String::from("hmvc"), vec![String::from("0")]),
+                (String::from("noop"), vec![]),
+            ]
+        );
+
+        let written = concat!(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ",
+            "bm e4 d4; am a4 h4; id \"start\"; c0 \"first; with a semicolon\"; c9 \"last\"; ",
+            "acd 12; ce -35; pv e4 e5 Nf3; hmvc 0; noop;"
+        );
+        assert_eq!(epd.to_string(), written);
+        assert_eq!(written.parse::<Epd>().unwrap().to_string(), written);
+    }
+
+    #[test]
+    fn no_operations() {
+        let epd: Epd = "4k3/8/8/8/8/8/8/4K3 b - -".parse().unwrap();
+        assert_eq!(epd.to_string(), "4k3/8/8/8/8/8/8/4K3 b - -");
+    }
+
+    #[test]
+    fn invalid_epds() {
+        let invalid = |s: &str| match s.parse::<Epd>() {
+            Ok(_) => panic!("\"{}\" parsed", s),
+            Err(err) => err,
+        };
+        let start = "4k3/8/8/8/8/8/8/4K3 w - -";
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 w -"),
+            EpdError::InvalidFen(FenError::WrongFieldCount(3))
+        );
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 w - - 0 1"),
+            EpdError::InvalidOperation(String::from("0 1"))
+        );
+        assert_eq!(
+            invalid(&format!("{} id \"open;", start)),
+            EpdError::UnterminatedString(String::from("\"open;"))
+        );
+        for (operations, operation) in &[
+            ("bm e4", "bm e4"),
+            ("bm;", "bm"),
+            ("id a b;", "id a b"),
+            ("acd x;", "acd x"),
+            ("ce 1.5;", "ce 1.5"),
+            ("c0;", "c0"),
+            (";", ""),
+            ("4x 1;", "4x"),
+        ] {
+            assert_eq!(
+                invalid(&format!("{} {}", start, operations)),
+                EpdError::InvalidOperation(String::from(*operation))
+            );
+        }
+        assert_eq!(
+            invalid(&format!("{} id \"a\"; id \"b\";", start)),
+            EpdError::DuplicateOpcode(String::from("id"))
+        );
+    }
+}
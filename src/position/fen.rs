@@ -0,0 +1,313 @@
+use crate::boardstructs::{BySquare, File, Rank, Shiftable, Square};
+use crate::piece::{ByColor, Color, Piece, PieceType};
+
+use super::{Bitboards, CastlingRights, Position};
+
+use std::fmt;
+
+// Forsyth-Edwards Notation, see
+// https://www.chessprogramming.org/Forsyth-Edwards_Notation
+
+#[derive(PartialEq, Debug)]
+pub enum FenError {
+    // FEN has six fields, or four when the clocks are left off
+    WrongFieldCount(usize),
+    InvalidBoard(String),
+    InvalidColor(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidClock(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(count) => {
+                write!(f, "expected 4 or 6 FEN fields, found {}", count)
+            }
+            FenError::InvalidBoard(s) => write!(f, "invalid FEN board \"{}\"", s),
+            FenError::InvalidColor(s) => write!(f, "invalid FEN side to move \"{}\"", s),
+            FenError::InvalidCastling(s) => write!(f, "invalid FEN castling rights \"{}\"", s),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid FEN en passant square \"{}\"", s),
+            FenError::InvalidClock(s) => write!(f, "invalid FEN move counter \"{}\"", s),
+        }
+    }
+}
+
+impl Position {
+    pub fn from_fen(fen: &str) -> Result<Position, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let board = parse_board(fields[0])
+            .ok_or_else(|| FenError::InvalidBoard(String::from(fields[0])))?;
+
+        let current_player = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            s => return Err(FenError::InvalidColor(String::from(s))),
+        };
+
+        let castling = CastlingRights::from_fen(fields[2], &board)
+            .ok_or_else(|| FenError::InvalidCastling(String::from(fields[2])))?;
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            s => match s.parse::<Square>() {
+                Ok(sqr) if is_en_passant_square(&board, current_player, sqr) => Some(sqr),
+                _ => return Err(FenError::InvalidEnPassant(String::from(s))),
+            },
+        };
+
+        let (halfmove_clock, fullmove_number) = if fields.len() == 6 {
+            let clock = |s: &str| {
+                s.parse::<u16>()
+                    .map_err(|_| FenError::InvalidClock(String::from(s)))
+            };
+            let fullmove_number = clock(fields[5])?;
+            if fullmove_number == 0 {
+                return Err(FenError::InvalidClock(String::from(fields[5])));
+            }
+            (clock(fields[4])?, fullmove_number)
+        } else {
+            (0, 1)
+        };
+
+        Ok(Position {
+            board,
+            bitboards: Bitboards::from(&board),
+            current_player,
+            castling,
+            en_passant,
+            halfmove_clock,
+            fullmove_number,
+        })
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for &rank in Rank::ALL.iter().rev() {
+            let mut empty = 0;
+            for sqr in rank.squares() {
+                let piece = self.piece_on(sqr);
+                if piece == Piece::None {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                fen.push(char::from(piece));
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank != Rank::new(0) {
+                fen.push('/');
+            }
+        }
+
+        fen.push(' ');
+        fen.push(match self.current_player {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        fen.push_str(&self.castling.to_fen(&self.board));
+
+        fen.push(' ');
+        match self.en_passant {
+            Some(sqr) => fen.push_str(&sqr.to_string()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+}
+
+// Ranks 8 to 1 separated by '/', each listing its pieces from the a-file with
+// runs of empty squares as digits. Besides the layout, each side needs
+// exactly one king and pawns can't be on the first or last rank.
+fn parse_board(s: &str) -> Option<BySquare<Piece>> {
+    let mut board = BySquare::new([Piece::None; 64]);
+    let ranks: Vec<&str> = s.split('/').collect();
+    if ranks.len() != 8 {
+        return None;
+    }
+    for (&rank, rank_str) in Rank::ALL.iter().rev().zip(ranks) {
+        let mut file = 0;
+        for c in rank_str.chars() {
+            if file >= 8 {
+                return None;
+            }
+            match c {
+                '1'..='8' => file += c as u8 - b'0',
+                _ => {
+                    let piece = Piece::try_from_char(c)?;
+                    board[Square::from_coords(rank, File::new(file))] = piece;
+                    file += 1;
+                }
+            }
+        }
+        if file != 8 {
+            return None;
+        }
+    }
+
+    let mut kings = ByColor::new(0, 0);
+    for (sqr, piece) in board.iter() {
+        match piece.split() {
+            Some((color, PieceType::King)) => kings[color] += 1,
+            Some((_, PieceType::Pawn))
+                if sqr.rank() == Rank::new(0) || sqr.rank() == Rank::new(7) =>
+            {
+                return None
+            }
+            _ => (),
+        }
+    }
+    if kings != ByColor::new(1, 1) {
+        return None;
+    }
+    Some(board)
+}
+
+// En passant squares are always just behind the pawn that moved two squares,
+// on the sixth rank from the side to move's point of view, and the pawn
+// skipped over it from an empty square
+fn is_en_passant_square(board: &BySquare<Piece>, current_player: Color, sqr: Square) -> bool {
+    let them = !current_player;
+    let pushed_pawn = sqr.shift(them.pawn_direction()).map(|sqr| board[sqr]);
+    let start = sqr
+        .shift(current_player.pawn_direction())
+        .map(|sqr| board[sqr]);
+    sqr.rank() == Rank::new(5).relative_to(current_player)
+        && board[sqr] == Piece::None
+        && start == Some(Piece::None)
+        && pushed_pawn == Some(Piece::new(them, PieceType::Pawn))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn starting_position() {
+        assert_eq!(Position::starting_position().to_fen(), STARTING_FEN);
+        let position = Position::from_fen(STARTING_FEN).unwrap();
+        assert_eq!(position.board, Position::starting_position().board);
+        assert_eq!(position.current_player(), Color::White);
+        assert_eq!(position.castling(), CastlingRights::standard());
+        assert_eq!(
+            Position::chess960_starting_position(0).unwrap().to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        for fen in &[
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbqkbnr/pppp1ppp/8/8/3pP3/8/PPP2PPP/RNBQKBNR b Kq e3 0 3",
+            "4k3/8/8/8/8/8/8/4K3 b - - 99 150",
+        ] {
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), *fen);
+        }
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // Shredder-FEN is read as well, X-FEN is written
+        let position =
+            Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1").unwrap();
+        assert_eq!(position.castling(), CastlingRights::standard());
+        assert_eq!(position.to_fen(), STARTING_FEN);
+        let position = Position::from_fen("r3k1rr/8/8/8/8/8/8/R3K1RR w GAk - 0 1").unwrap();
+        assert_eq!(position.to_fen(), "r3k1rr/8/8/8/8/8/8/R3K1RR w GQk - 0 1");
+    }
+
+    #[test]
+    fn missing_clocks() {
+        let position = Position::from_fen("4k3/8/8/8/8/8/8/4K3 b - -").unwrap();
+        assert_eq!(position.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn en_passant() {
+        let position =
+            Position::from_fen("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3")
+                .unwrap();
+        assert_eq!(position.en_passant(), Some(Square::F6));
+    }
+
+    #[test]
+    fn invalid_fens() {
+        let invalid = |fen: &str| Position::from_fen(fen).err().unwrap();
+        assert_eq!(invalid(""), FenError::WrongFieldCount(0));
+        assert_eq!(
+            invalid("8/8/8/8/8/8/8/8 w - - 0"),
+            FenError::WrongFieldCount(5)
+        );
+        for board in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR",
+            "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNx",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR",
+            "rnbqkbnp/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+        ] {
+            assert_eq!(
+                invalid(&format!("{} w KQkq - 0 1", board)),
+                FenError::InvalidBoard(String::from(*board))
+            );
+        }
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 x - - 0 1"),
+            FenError::InvalidColor(String::from("x"))
+        );
+        for castling in &["KK", "KQkqX", "-K", "k"] {
+            assert_eq!(
+                invalid(&format!("4k3/8/8/8/8/8/8/R3K2R w {} - 0 1", castling)),
+                FenError::InvalidCastling(String::from(*castling))
+            );
+        }
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 w KQkq e6 0 1"),
+            FenError::InvalidCastling(String::from("KQkq"))
+        );
+        for (fen, en_passant) in &[
+            ("4k3/8/8/4p3/8/8/8/4K3 w - e3 0 1", "e3"),
+            ("4k3/8/8/8/8/8/8/4K3 w - e6 0 1", "e6"),
+            ("4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1", "e6"),
+            ("4k3/8/4n3/4p3/8/8/8/4K3 w - e6 0 1", "e6"),
+            ("4k3/8/8/4P3/8/8/8/4K3 w - e6 0 1", "e6"),
+        ] {
+            assert_eq!(
+                invalid(fen),
+                FenError::InvalidEnPassant(String::from(*en_passant))
+            );
+        }
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 w - - x 1"),
+            FenError::InvalidClock(String::from("x"))
+        );
+        assert_eq!(
+            invalid("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+            FenError::InvalidClock(String::from("0"))
+        );
+    }
+}
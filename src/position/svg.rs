@@ -0,0 +1,444 @@
+use crate::bitboard::Bitboard;
+use crate::boardstructs::{File, Rank, Square};
+use crate::moves::Move;
+use crate::piece::{Color, PieceType};
+
+use super::Position;
+
+use std::fmt;
+
+// Board geometry in SVG user units, with a margin around the board for the
+// coordinates
+const SQUARE_SIZE: u32 = 45;
+const MARGIN: u32 = 20;
+const BOARD_SIZE: u32 = 8 * SQUARE_SIZE;
+
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const LAST_MOVE: &str = "#cdd26a";
+const HIGHLIGHT: &str = "#3a8fd9";
+
+// One glyph per piece type, drawn in a 45x45 box. Fill and stroke come from
+// the <use> that places them, so the same glyph serves both colors.
+const PIECE_GLYPHS: [(PieceType, &str); 6] = [
+    (
+        PieceType::Pawn,
+        r#"<circle cx="22.5" cy="15" r="6"/><path d="M16 35c0-8 3-12 6.5-14 3.5 2 6.5 6 6.5 14z"/><rect x="12" y="34" width="21" height="4" rx="1"/>"#,
+    ),
+    (
+        PieceType::Knight,
+        r#"<path d="M14 34c0-7 3-11 7-14-3 0-6 2-8 4l-3-3c2-5 6-10 12-12l1-3 3 3c5 2 8 8 8 17v8z"/><rect x="11" y="34" width="24" height="4" rx="1"/>"#,
+    ),
+    (
+        PieceType::Bishop,
+        r#"<circle cx="22.5" cy="7" r="2.5"/><path d="M22.5 9.5c-5 5-8 10-8 14 0 4 3 6 8 6s8-2 8-6c0-4-3-9-8-14z"/><path d="M17 30h11l2 4H15z"/><rect x="11" y="34" width="23" height="4" rx="1"/>"#,
+    ),
+    (
+        PieceType::Rook,
+        r#"<path d="M12 10h4v3h4.5v-3h4v3h4.5v-3h4v8h-3v16h-15v-16h-3z"/><rect x="10" y="34" width="25" height="4" rx="1"/>"#,
+    ),
+    (
+        PieceType::Queen,
+        r#"<path d="M9 14l5 16h17l5-16-7 8-6.5-11-6.5 11z"/><circle cx="9" cy="12" r="2"/><circle cx="22.5" cy="9" r="2"/><circle cx="36" cy="12" r="2"/><path d="M13 31h19l2 6H11z"/>"#,
+    ),
+    (
+        PieceType::King,
+        r#"<path d="M21 5h3v3h3v3h-3v4h-3v-4h-3v-3h3z"/><path d="M12 30c-4-6-2-13 4-13 3 0 5 2 6.5 4 1.5-2 3.5-4 6.5-4 6 0 8 7 4 13z"/><path d="M12 31h21l2 6H10z"/>"#,
+    ),
+];
+
+// Writes a string with the characters that are special in XML escaped, so
+// that it can't end the attribute or element it's written into
+struct Escaped<'a>(&'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '&' => write!(f, "&amp;")?,
+                '<' => write!(f, "&lt;")?,
+                '>' => write!(f, "&gt;")?,
+                '"' => write!(f, "&quot;")?,
+                '\'' => write!(f, "&apos;")?,
+                _ => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Arrow {
+    from: Square,
+    to: Square,
+    // any SVG color, e.g. "#15781b" or "red", escaped when written
+    color: String,
+}
+
+// A self-contained SVG drawing of a position, for embedding in HTML. The
+// piece glyphs are included in the output rather than relying on fonts, and
+// the output only depends on what is drawn, so it can be snapshot tested.
+pub struct Svg<'a> {
+    position: &'a Position,
+    // prepended to every id in the drawing, see id_prefix
+    id_prefix: String,
+    flipped: bool,
+    last_move: Option<Move>,
+    highlights: Bitboard,
+    arrows: Vec<Arrow>,
+}
+
+impl Position {
+    pub fn svg(&self) -> Svg<'_> {
+        Svg {
+            position: self,
+            id_prefix: String::from("board"),
+            flipped: false,
+            last_move: None,
+            highlights: Bitboard::new(),
+            arrows: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Svg<'a> {
+    // Ids in an HTML document are shared by every SVG in it, so diagrams on
+    // the same page each need their own prefix. Ids are used in url(#...)
+    // references without escaping, so any character other than a letter,
+    // digit, '-' or '_' is replaced with '_', and "id-" is put in front of a
+    // prefix that doesn't start with a letter.
+    pub fn id_prefix(mut self, prefix: &str) -> Self {
+        let sanitized: String = prefix
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.id_prefix = if sanitized.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            sanitized
+        } else {
+            format!("id-{}", sanitized)
+        };
+        self
+    }
+
+    // From black's side, with rank 1 at the top and the h-file on the left
+    pub fn flipped(mut self) -> Self {
+        self.flipped = true;
+        self
+    }
+
+    pub fn last_move(mut self, mv: Move) -> Self {
+        self.last_move = Some(mv);
+        self
+    }
+
+    pub fn highlight(mut self, squares: Bitboard) -> Self {
+        self.highlights |= squares;
+        self
+    }
+
+    // Arrows are drawn in the order they are added, on top of the pieces
+    pub fn arrow(mut self, from: Square, to: Square, color: &str) -> Self {
+        self.arrows.push(Arrow {
+            from,
+            to,
+            color: String::from(color),
+        });
+        self
+    }
+
+    // The top left corner of sqr
+    fn corner(&self, sqr: Square) -> (u32, u32) {
+        let (rank, file) = (u8::from(sqr.rank()) as u32, u8::from(sqr.file()) as u32);
+        let (column, row) = if self.flipped {
+            (7 - file, rank)
+        } else {
+            (file, 7 - rank)
+        };
+        (MARGIN + column * SQUARE_SIZE, MARGIN + row * SQUARE_SIZE)
+    }
+
+    fn center(&self, sqr: Square) -> (f32, f32) {
+        let (x, y) = self.corner(sqr);
+        let half = SQUARE_SIZE as f32 / 2.0;
+        (x as f32 + half, y as f32 + half)
+    }
+
+    fn write_square(
+        &self,
+        f: &mut fmt::Formatter,
+        sqr: Square,
+        color: &str,
+        opacity: &str,
+    ) -> fmt::Result {
+        let (x, y) = self.corner(sqr);
+        write!(
+            f,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"{}/>"#,
+            x, y, SQUARE_SIZE, SQUARE_SIZE, color, opacity
+        )
+    }
+
+    fn write_coordinates(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            r##"<g font-family="sans-serif" font-size="12" text-anchor="middle" fill="#333">"##
+        )?;
+        for &file in &File::ALL {
+            let (x, _) = self.center(Square::from_coords(Rank::new(0), file));
+            write!(
+                f,
+                r#"<text x="{}" y="{}">{}</text>"#,
+                x,
+                MARGIN + BOARD_SIZE + 14,
+                file
+            )?;
+        }
+        for &rank in &Rank::ALL {
+            let (_, y) = self.center(Square::from_coords(rank, File::new(0)));
+            write!(
+                f,
+                r#"<text x="{}" y="{}">{}</text>"#,
+                MARGIN / 2,
+                y + 4.0,
+                rank
+            )?;
+        }
+        write!(f, "</g>")
+    }
+
+    // A shaft from the center of one square and a head ending at the center
+    // of the other
+    fn write_arrow(&self, f: &mut fmt::Formatter, arrow: &Arrow) -> fmt::Result {
+        const HEAD_LENGTH: f32 = 18.0;
+        const HEAD_WIDTH: f32 = 10.0;
+        let (x1, y1) = self.center(arrow.from);
+        let (x2, y2) = self.center(arrow.to);
+        let length = ((x2 - x1) * (x2 - x1) + (y2 - y1) * (y2 - y1)).sqrt();
+        if length == 0.0 {
+            return Ok(());
+        }
+        let (dx, dy) = ((x2 - x1) / length, (y2 - y1) / length);
+        let (base_x, base_y) = (x2 - dx * HEAD_LENGTH, y2 - dy * HEAD_LENGTH);
+        write!(
+            f,
+            r#"<g fill="{0}" stroke="{0}" opacity="0.8"><line x1="{1:.1}" y1="{2:.1}" x2="{3:.1}" y2="{4:.1}" stroke-width="8" stroke-linecap="round"/><polygon points="{5:.1},{6:.1} {7:.1},{8:.1} {9:.1},{10:.1}" stroke="none"/></g>"#,
+            Escaped(&arrow.color),
+            x1,
+            y1,
+            base_x,
+            base_y,
+            x2,
+            y2,
+            base_x - dy * HEAD_WIDTH,
+            base_y + dx * HEAD_WIDTH,
+            base_x + dy * HEAD_WIDTH,
+            base_y - dx * HEAD_WIDTH,
+        )
+    }
+}
+
+impl<'a> fmt::Display for Svg<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let size = 2 * MARGIN + BOARD_SIZE;
+        write!(
+            f,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" viewBox="0 0 {0} {0}" width="{0}" height="{0}">"#,
+            size
+        )?;
+
+        write!(f, "<defs>")?;
+        for (piece_type, glyph) in PIECE_GLYPHS.iter() {
+            write!(
+                f,
+                r#"<g id="{}-piece-{}" stroke-width="1.5" stroke-linejoin="round">{}</g>"#,
+                self.id_prefix,
+                char::from(*piece_type),
+                glyph
+            )?;
+        }
+        write!(
+            f,
+            r##"<radialGradient id="{}-check"><stop offset="0%" stop-color="#ff0000"/><stop offset="25%" stop-color="#e70000"/><stop offset="89%" stop-color="#a50000" stop-opacity="0"/></radialGradient>"##,
+            self.id_prefix
+        )?;
+        write!(f, "</defs>")?;
+
+        for &sqr in &Square::ALL {
+            let color = if Bitboard::LIGHT_SQUARES.contains(sqr) {
+                LIGHT_SQUARE
+            } else {
+                DARK_SQUARE
+            };
+            self.write_square(f, sqr, color, "")?;
+        }
+        if let Some(mv) = self.last_move {
+            self.write_square(f, mv.from(), LAST_MOVE, r#" opacity="0.6""#)?;
+            self.write_square(f, mv.to(), LAST_MOVE, r#" opacity="0.6""#)?;
+        }
+        for sqr in self.highlights {
+            self.write_square(f, sqr, HIGHLIGHT, r#" opacity="0.5""#)?;
+        }
+        if let Some(king) = self.position.checked_king() {
+            let check = format!("url(#{}-check)", self.id_prefix);
+            self.write_square(f, king, &check, "")?;
+        }
+        self.write_coordinates(f)?;
+
+        for &sqr in &Square::ALL {
+            if let Some((color, piece_type)) = self.position.piece_on(sqr).split() {
+                let (x, y) = self.corner(sqr);
+                let (fill, stroke) = match color {
+                    Color::White => ("#fff", "#000"),
+                    Color::Black => ("#000", "#000"),
+                };
+                write!(
+                    f,
+                    r##"<use xlink:href="#{}-piece-{}" x="{}" y="{}" fill="{}" stroke="{}"/>"##,
+                    self.id_prefix,
+                    char::from(piece_type),
+                    x,
+                    y,
+                    fill,
+                    stroke
+                )?;
+            }
+        }
+
+        for arrow in &self.arrows {
+            self.write_arrow(f, arrow)?;
+        }
+        write!(f, "</svg>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::piece::Piece;
+
+    #[test]
+    fn starting_position() {
+        let svg = Position::starting_position().svg().to_string();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<use ").count(), 32);
+        assert_eq!(svg.matches(r#"<g id="board-piece-"#).count(), 6);
+        // a1 is dark, in the bottom left corner
+        assert!(svg.contains(r##"<rect x="20" y="335" width="45" height="45" fill="#b58863"/>"##));
+        assert!(svg.contains(
+            r##"<use xlink:href="#board-piece-r" x="20" y="335" fill="#fff" stroke="#000"/>"##
+        ));
+        assert!(!svg.contains("-check)"));
+        // the same drawing every time
+        assert_eq!(svg, Position::starting_position().svg().to_string());
+    }
+
+    #[test]
+    fn flipped() {
+        let svg = Position::starting_position().svg().flipped().to_string();
+        // a1 in the top right corner, with the black rooks at the bottom
+        assert!(svg.contains(
+            r##"<use xlink:href="#board-piece-r" x="335" y="20" fill="#fff" stroke="#000"/>"##
+        ));
+        assert!(svg.contains(
+            r##"<use xlink:href="#board-piece-r" x="20" y="335" fill="#000" stroke="#000"/>"##
+        ));
+        assert!(svg.contains(r#"<text x="357.5" y="394">a</text>"#));
+    }
+
+    #[test]
+    fn highlights_and_arrows() {
+        let mut position = Position::starting_position();
+        position.set_piece_on(Piece::None, Square::D2);
+        position.set_piece_on(Piece::BlackBishop, Square::B4);
+        let svg = position
+            .svg()
+            .highlight(Bitboard::from(Square::H3))
+            .arrow(Square::E2, Square::E4, "green")
+            .to_string();
+        assert!(svg.contains(
+            r#"<rect x="200" y="335" width="45" height="45" fill="url(#board-check)"/>"#
+        ));
+        assert!(svg.contains(
+            r##"<rect x="335" y="245" width="45" height="45" fill="#3a8fd9" opacity="0.5"/>"##
+        ));
+        assert!(svg.contains(concat!(
+            r#"<g fill="green" stroke="green" opacity="0.8">"#,
+            r#"<line x1="222.5" y1="312.5" x2="222.5" y2="240.5" stroke-width="8" stroke-linecap="round"/>"#,
+            r#"<polygon points="222.5,222.5 232.5,240.5 212.5,240.5" stroke="none"/></g>"#
+        )));
+    }
+
+    #[test]
+    fn arrow_colors_are_escaped() {
+        let svg = Position::starting_position()
+            .svg()
+            .arrow(
+                Square::E2,
+                Square::E4,
+                r#"red"/><script>alert('&')</script>"#,
+            )
+            .to_string();
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains(
+            r#"<g fill="red&quot;/&gt;&lt;script&gt;alert(&apos;&amp;&apos;)&lt;/script&gt;" "#
+        ));
+    }
+
+    #[test]
+    fn id_prefixes() {
+        let mut position = Position::starting_position();
+        position.set_piece_on(Piece::None, Square::D2);
+        position.set_piece_on(Piece::BlackBishop, Square::B4);
+        let svg = position.svg().id_prefix("game-2").to_string();
+        assert!(svg.contains(r#"<g id="game-2-piece-k" "#));
+        assert!(svg.contains(r#"<radialGradient id="game-2-check">"#));
+        assert!(svg.contains(r##"xlink:href="#game-2-piece-k""##));
+        assert!(svg.contains(r#"fill="url(#game-2-check)""#));
+        assert!(!svg.contains("board-"));
+    }
+
+    #[test]
+    fn invalid_id_prefixes() {
+        let svg = Position::starting_position()
+            .svg()
+            .id_prefix("2\"/><script>")
+            .to_string();
+        assert!(!svg.contains("<script>"));
+        assert!(svg.contains(r#"<g id="id-2____script_-piece-k" "#));
+        let svg = Position::starting_position()
+            .svg()
+            .id_prefix("")
+            .to_string();
+        assert!(svg.contains(r#"<g id="id--piece-k" "#));
+    }
+
+    #[test]
+    fn last_move() {
+        let mut position = Position::starting_position();
+        let mv: Move = "g1f3".parse().unwrap();
+        position.make_move(mv);
+        let svg = position.svg().last_move(mv).to_string();
+        assert_eq!(svg.matches(r##"fill="#cdd26a" opacity="0.6""##).count(), 2);
+        assert!(svg.contains(
+            r##"<rect x="290" y="335" width="45" height="45" fill="#cdd26a" opacity="0.6"/>"##
+        ));
+        assert!(svg.contains(
+            r##"<rect x="245" y="245" width="45" height="45" fill="#cdd26a" opacity="0.6"/>"##
+        ));
+        // flipping moves the highlights with the squares
+        let svg = position.svg().flipped().last_move(mv).to_string();
+        assert!(svg.contains(
+            r##"<rect x="65" y="20" width="45" height="45" fill="#cdd26a" opacity="0.6"/>"##
+        ));
+        assert!(svg.contains(
+            r##"<rect x="110" y="110" width="45" height="45" fill="#cdd26a" opacity="0.6"/>"##
+        ));
+    }
+}
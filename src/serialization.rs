@@ -0,0 +1,160 @@
+use crate::bitboard::Bitboard;
+use crate::boardstructs::{File, Rank, Square};
+use crate::moves::Move;
+use crate::position::Position;
+
+use serde::de::{self, Deserialize, Deserializer, Unexpected};
+use serde::ser::{Serialize, Serializer};
+
+// Serde support, behind the serde feature. Coordinates and moves use their
+// usual text forms ("e4", "e2e4"), positions are FEN and bitboards are hex
+// strings, so the JSON stays readable. Piece, Color and PieceType derive
+// their impls.
+
+// Types that serialize as their Display form and deserialize with FromStr
+macro_rules! serde_via_str {
+    ($($t:ty => $expected:expr),* $(,)?) => {$(
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse()
+                    .map_err(|_| de::Error::invalid_value(Unexpected::Str(&s), &$expected))
+            }
+        }
+    )*};
+}
+
+serde_via_str! {
+    Square => "a square like \"e4\"",
+    Rank => "a rank from \"1\" to \"8\"",
+    File => "a file from \"a\" to \"h\"",
+    Move => "a move like \"e2e4\" or \"e7e8q\"",
+}
+
+impl Serialize for Bitboard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:#018x}", u64::from(*self)))
+    }
+}
+
+impl<'de> Deserialize<'de> for Bitboard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Bitboard, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        // from_str_radix alone would also take a sign or fewer digits
+        s.strip_prefix("0x")
+            .filter(|hex| hex.len() == 16 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .map(Bitboard::from)
+            .ok_or_else(|| {
+                de::Error::invalid_value(
+                    Unexpected::Str(&s),
+                    &"a hex string like \"0x00000000ffff0000\"",
+                )
+            })
+    }
+}
+
+impl Serialize for Position {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_fen())
+    }
+}
+
+impl<'de> Deserialize<'de> for Position {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Position, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Position::from_fen(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::piece::{Color, Piece, PieceType};
+
+    use serde::de::DeserializeOwned;
+    use std::fmt::Debug;
+
+    // Checks the JSON form and that both JSON and bincode give back value
+    fn round_trip<T>(value: T, json: &str)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        assert_eq!(serde_json::to_string(&value).unwrap(), json);
+        assert_eq!(serde_json::from_str::<T>(json).unwrap(), value);
+        let bytes = bincode::serialize(&value).unwrap();
+        assert_eq!(bincode::deserialize::<T>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn coordinates() {
+        round_trip(Square::E4, r#""e4""#);
+        round_trip(Rank::new(3), r#""4""#);
+        round_trip(File::new(4), r#""e""#);
+        assert!(serde_json::from_str::<Square>(r#""e9""#).is_err());
+        assert!(serde_json::from_str::<Square>("28").is_err());
+    }
+
+    #[test]
+    fn pieces() {
+        round_trip(Piece::WhiteKnight, r#""WhiteKnight""#);
+        round_trip(Color::Black, r#""Black""#);
+        round_trip(PieceType::Queen, r#""Queen""#);
+    }
+
+    #[test]
+    fn moves() {
+        round_trip(Move::new(Square::E2, Square::E4, None), r#""e2e4""#);
+        round_trip(
+            Move::new(Square::E7, Square::E8, Some(PieceType::Queen)),
+            r#""e7e8q""#,
+        );
+        // captures too, since moves don't record whether they capture
+        round_trip(Move::new(Square::E4, Square::D5, None), r#""e4d5""#);
+        round_trip(
+            Move::new(Square::G7, Square::H8, Some(PieceType::Knight)),
+            r#""g7h8n""#,
+        );
+        assert!(serde_json::from_str::<Move>(r#""e2""#).is_err());
+    }
+
+    #[test]
+    fn bitboards() {
+        round_trip(Bitboard::from(Rank::new(1)), r#""0x000000000000ff00""#);
+        round_trip(Bitboard::new(), r#""0x0000000000000000""#);
+        assert!(serde_json::from_str::<Bitboard>(r#""ff00""#).is_err());
+        assert!(serde_json::from_str::<Bitboard>(r#""0xfg""#).is_err());
+        assert!(serde_json::from_str::<Bitboard>(r#""0x+ff""#).is_err());
+        assert!(serde_json::from_str::<Bitboard>(r#""0xff00""#).is_err());
+        assert!(serde_json::from_str::<Bitboard>(r#""0x+00000000000ff00""#).is_err());
+    }
+
+    #[test]
+    fn positions() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let position = Position::from_fen(fen).unwrap();
+        let json = serde_json::to_string(&position).unwrap();
+        assert_eq!(json, format!("\"{}\"", fen));
+        assert_eq!(
+            serde_json::from_str::<Position>(&json).unwrap().to_fen(),
+            fen
+        );
+        let bytes = bincode::serialize(&position).unwrap();
+        assert_eq!(
+            bincode::deserialize::<Position>(&bytes).unwrap().to_fen(),
+            fen
+        );
+
+        let err = serde_json::from_str::<Position>(r#""8/8/8/8 w - - 0 1""#)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid FEN board"));
+    }
+}